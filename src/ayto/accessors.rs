@@ -1,20 +1,117 @@
 use crate::prelude::*;
 
-impl<'a> Season<'a> {
+impl Season {
     /// Fetches the corresponding id of a name from its idmap.
     pub(super) fn id(&self, k: &str, d: &HashMap<String, usize>) -> Result<usize> {
         d.get(k).cloned().context(format!("invalid name {k}"))
     }
 
+    /// Fetches the worldview for this season, provided that its worlds have been materialised.
+    pub(super) fn view(&self) -> Result<&Arc<Worldview>> {
+        self.worldview
+            .as_ref()
+            .context("worlds have not been materialised; use `materialise` first")
+    }
+
+    /// Fetches the ids of a couple, whichever order its names are given in.
+    pub(super) fn couple_id(&self, couple: CoupleInput) -> Result<Couple> {
+        let (a, b) = couple;
+        match (self.ms.get(a), self.fs.get(b)) {
+            (Some(&m), Some(&f)) => Ok((m, f)),
+            _ => Ok((self.id(b, &self.ms)?, self.id(a, &self.fs)?)),
+        }
+    }
+
+    /// Returns every couple that could be a perfect match, listing each couple only once.
+    pub(super) fn couples(&self) -> Vec<Couple> {
+        (0..self.m.len())
+            .cartesian_product(0..self.f.len())
+            .filter(|&couple| self.is_couple(couple))
+            .collect()
+    }
+
+    /// Determines whether a pair of ids is a couple in its canonical orientation.
+    ///
+    /// In a single pool, the couple `(a, b)` is the same as `(b, a)`, so only the orientation with `a < b` counts.
+    pub(super) fn is_couple(&self, couple: Couple) -> bool {
+        let (m, f) = couple;
+        match self.kind {
+            SeasonKind::Bipartite => true,
+            SeasonKind::NonBipartite => m < f,
+        }
+    }
+
+    /// Orients a couple canonically, so that in a single pool `(a, b)` and `(b, a)` are recorded as the same couple.
+    pub(super) fn canonical(&self, couple: Couple) -> Couple {
+        let (m, f) = couple;
+        match self.is_couple(couple) {
+            true => (m, f),
+            _ => (f, m),
+        }
+    }
+
+    /// Converts a world into the list of couples that make it up.
+    pub(super) fn couples_of(&self, world: &[u8]) -> Vec<Couple> {
+        world
+            .iter()
+            .map(|&f| f as usize)
+            .enumerate()
+            .filter(|&couple| self.is_couple(couple))
+            .collect()
+    }
+
+    /// Converts a world into a match strip that could be sat at a ceremony.
+    ///
+    /// Anybody who shares their match with an earlier row is left without a seat, since every column sits with exactly
+    /// one row at a ceremony. In a single pool, each couple is only recorded against its lower id.
+    pub(super) fn ceremony_of(&self, world: &[u8]) -> Vec<usize> {
+        let mut seated = vec![false; self.f.len()];
+        world
+            .iter()
+            .map(|&f| f as usize)
+            .enumerate()
+            .map(|(m, f)| match seated[f] || !self.is_couple((m, f)) {
+                true => NO_MATCH,
+                _ => {
+                    seated[f] = true;
+                    f
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the number of remaining worlds in which the given couple is a perfect match.
+    pub fn count(&self, couple: CoupleInput) -> Result<u64> {
+        Ok(self.counts[self.couple_id(couple)?])
+    }
+
+    /// Returns the number of remaining worlds in which each couple is a perfect match, indexed by row and column.
+    ///
+    /// In a single pool, the table is symmetric.
+    pub fn counts(&self) -> ArrayView2<'_, u64> {
+        self.counts.view()
+    }
+
+    /// Returns the list of contestants that make up the rows of the table.
+    pub fn rows(&self) -> &[String] {
+        &self.m
+    }
+
+    /// Returns the list of contestants that make up the columns of the table.
+    pub fn columns(&self) -> &[String] {
+        &self.f
+    }
+
     /// Returns a list of couples that are correct in all remaining worlds.
     pub fn known_couples(&self) -> Vec<(String, String)> {
-        self.distribution
-            // Take all couples in the bipartite table that are confirmed to be true down to f32 effects.
-            .mapv(|p| (p - 1.0).abs() <= f32::MIN)
+        let nw = self.num_worlds() as u64;
+        self.counts
+            // Take all couples in the bipartite table that are in every remaining world.
+            .mapv(|count| nw > 0 && count == nw)
             .indexed_iter()
             // Convert each couple to their named representation.
             .filter_map(|((m, f), &b)| {
-                if b {
+                if b && self.is_couple((m, f)) {
                     Some((self.m[m].clone(), self.f[f].clone()))
                 } else {
                     None
@@ -23,9 +120,61 @@ impl<'a> Season<'a> {
             .collect()
     }
 
+    /// Returns the engine that this season's worlds were last counted with, if they could be counted at all.
+    ///
+    /// Unmaterialised seasons that are too large to count without enumerating them are left uncounted until they are
+    /// materialised.
+    pub fn engine(&self) -> Option<Engine> {
+        self.engine
+    }
+
+    /// Builds an enumerator that is constrained by all of the evidence recorded so far.
+    pub(super) fn enumerator(&self) -> Enumerator {
+        let mut enumerator = Enumerator::new(self.kind, self.m.len(), self.f.len());
+        self.evidence.iter().for_each(|evidence| {
+            enumerator.constrain(evidence);
+        });
+        enumerator
+    }
+
+    /// Returns the evidence recorded against this season so far, in order.
+    pub fn evidence(&self) -> &[Evidence] {
+        &self.evidence
+    }
+
+    /// Returns whether the worlds of this season have been materialised.
+    pub fn is_materialised(&self) -> bool {
+        self.worldview.is_some()
+    }
+
+    /// Returns the settings that this season samples its worlds with, if it is in approximate mode.
+    pub fn sampling(&self) -> Option<Sampling> {
+        self.sampling
+    }
+
+    /// Estimates the probability that a given number of the remaining worlds make up, with the half-width of its 95%
+    /// confidence interval.
+    ///
+    /// Exact seasons have no margin of error. In approximate mode, the interval treats each sample as independent, and
+    /// when no samples or every sample are counted, the margin follows the rule of three instead.
+    pub fn estimate(&self, count: u64) -> (f64, f64) {
+        let nw = self.num_worlds() as f64;
+        let p = count as f64 / nw;
+        match self.sampling {
+            None => (p, 0.0),
+            Some(_) if count == 0 || count as f64 == nw => (p, 3.0 / nw),
+            Some(_) => (p, 1.96 * (p * (1.0 - p) / nw).sqrt()),
+        }
+    }
+
+    /// Returns the kind of matching that this season is played over.
+    pub fn kind(&self) -> SeasonKind {
+        self.kind
+    }
+
     /// Returns the number of worlds remaining on this season.
     pub fn num_worlds(&self) -> usize {
-        self.worlds.len()
+        self.total as usize
     }
 
     /// Returns the number of couples in this season.
@@ -34,10 +183,14 @@ impl<'a> Season<'a> {
     }
 
     /// Get the worlds that are currently consistent with this season.
-    pub fn worlds(&self) -> Vec<Vec<CoupleOutput>> {
-        self.worlds
+    pub fn worlds(&self) -> Result<Vec<Vec<CoupleOutput>>> {
+        let view = self.view()?;
+        let worlds = self
+            .worlds
             .iter()
-            .map(|k| self.worldview[*k].clone().into_iter().enumerate().map(|(m, f)| (self.m[m].clone(), self.f[f].clone())).collect())
-            .collect()
+            .map(|k| self.couples_of(view.get(k)).into_iter().map(|(m, f)| (self.m[m].clone(), self.f[f].clone())).collect())
+            .collect();
+
+        Ok(worlds)
     }
 }
@@ -0,0 +1,150 @@
+use crate::prelude::*;
+
+/// A fixed-size set of ids, packed into 64-bit words.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bitset {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitset {
+    /// Creates an empty set over the ids 0..len.
+    pub fn new(len: usize) -> Bitset {
+        Bitset {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    /// Creates a set containing every id in 0..len.
+    pub fn full(len: usize) -> Bitset {
+        let mut set = Bitset {
+            words: vec![u64::MAX; len.div_ceil(64)],
+            len,
+        };
+        set.trim();
+        set
+    }
+
+    /// Keeps only those ids that are also in `other`.
+    pub fn and_assign(&mut self, other: &Bitset) {
+        self.words
+            .iter_mut()
+            .zip(&other.words)
+            .for_each(|(lhs, rhs)| *lhs &= rhs);
+    }
+
+    /// Removes every id that is in `other`.
+    pub fn and_not_assign(&mut self, other: &Bitset) {
+        self.words
+            .iter_mut()
+            .zip(&other.words)
+            .for_each(|(lhs, rhs)| *lhs &= !rhs);
+    }
+
+    /// Returns whether the set contains an id.
+    pub fn contains(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Returns the number of ids in the set.
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns the number of ids in both this set and `other`, without building their intersection.
+    pub fn count_and(&self, other: &Bitset) -> usize {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .map(|(lhs, rhs)| (lhs & rhs).count_ones() as usize)
+            .sum()
+    }
+
+    /// Adds an id to the set.
+    pub fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    /// Returns an iterator over the ids in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || match word {
+                0 => None,
+                _ => {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(i * 64 + bit)
+                }
+            })
+        })
+    }
+
+    /// Returns a parallel iterator over the ids in the set.
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = usize> + '_ {
+        self.words.par_iter().enumerate().flat_map_iter(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || match word {
+                0 => None,
+                _ => {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(i * 64 + bit)
+                }
+            })
+        })
+    }
+
+    /// Returns the size of the universe that this set draws its ids from.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Removes an id from the set.
+    pub fn remove(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64));
+    }
+
+    /// Keeps only those ids for which the predicate holds.
+    pub fn retain(&mut self, mut f: impl FnMut(usize) -> bool) {
+        self.words.iter_mut().enumerate().for_each(|(i, word)| {
+            let mut ones = *word;
+            while ones != 0 {
+                let bit = ones.trailing_zeros() as usize;
+                ones &= ones - 1;
+                if !f(i * 64 + bit) {
+                    *word &= !(1 << bit);
+                }
+            }
+        });
+    }
+
+    /// Keeps only those ids for which the predicate holds, checking the ids in parallel.
+    pub fn par_retain(&mut self, f: impl Fn(usize) -> bool + Sync) {
+        self.words.par_iter_mut().enumerate().for_each(|(i, word)| {
+            let mut ones = *word;
+            while ones != 0 {
+                let bit = ones.trailing_zeros() as usize;
+                ones &= ones - 1;
+                if !f(i * 64 + bit) {
+                    *word &= !(1 << bit);
+                }
+            }
+        });
+    }
+
+    /// Clears any bits past the end of the universe.
+    fn trim(&mut self) {
+        if !self.len.is_multiple_of(64) {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1 << (self.len % 64)) - 1;
+            }
+        }
+    }
+}
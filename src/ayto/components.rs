@@ -0,0 +1,205 @@
+use crate::prelude::*;
+
+/// The largest number of worlds that a single component is allowed to enumerate.
+const MAX_COMPONENT_WORLDS: usize = 10_000_000;
+
+/// An independent block of a season, made up of contestants that can only be matched with each other.
+///
+/// Two contestants are in the same block if they could still be a perfect match, or if they are both linked to a
+/// ceremony that could still light up a beam. Nothing that happens in one block constrains any other block, so the
+/// worlds of a season are the cross product of the worlds of its blocks.
+#[derive(Clone, Debug)]
+struct Component {
+    /// The rows in this component, in order.
+    rows: Vec<usize>,
+    /// The columns in this component, in order.
+    cols: Vec<usize>,
+    /// The ceremonies that light up any beams in this component, restricted to its own rows and columns.
+    ceremonies: Vec<(Vec<usize>, usize)>,
+}
+
+/// Counts the worlds of a season, in total and per couple, one independent component at a time.
+///
+/// Components without any ceremonies are counted with a dynamic program, and the rest are enumerated on their own, or
+/// met in the middle if they are too large to enumerate. Counts are then combined multiplicatively, since every world of
+/// a component can be combined with every world of the others. Errors if any component is too large to count this way.
+pub fn count(
+    kind: SeasonKind,
+    allowed: &Array2<bool>,
+    ceremonies: &[(Vec<usize>, usize)],
+) -> Result<(u64, Array2<u64>)> {
+    let (rows, cols) = allowed.dim();
+    let Some(components) = split(kind, allowed, ceremonies) else {
+        return Ok((0, Array2::zeros((rows, cols))));
+    };
+
+    debug!("split into {} components", components.len());
+    let counted: Vec<(u64, Array2<u64>)> = components
+        .par_iter()
+        .map(|component| component.count(kind, allowed))
+        .collect::<Result<_>>()?;
+
+    let total = counted
+        .iter()
+        .try_fold(1u64, |total, &(t, _)| total.checked_mul(t))
+        .context("too many worlds to count")?;
+
+    // Each couple's count within its own component is scaled by the number of ways to complete every other component.
+    let mut counts = Array2::zeros((rows, cols));
+    for (component, (t, local)) in components.iter().zip(&counted) {
+        let others = match t {
+            0 => 0,
+            _ => total / t,
+        };
+        for ((i, j), &count) in local.indexed_iter() {
+            counts[(component.rows[i], component.cols[j])] = count * others;
+        }
+    }
+
+    Ok((total, counts))
+}
+
+/// Splits a season into its independent components, or returns `None` if some component can have no worlds at all.
+fn split(
+    kind: SeasonKind,
+    allowed: &Array2<bool>,
+    ceremonies: &[(Vec<usize>, usize)],
+) -> Option<Vec<Component>> {
+    let (rows, cols) = allowed.dim();
+
+    // In a single pool, rows and columns are the same contestants; otherwise, columns come after the rows.
+    let offset = match kind {
+        SeasonKind::Bipartite => rows,
+        SeasonKind::NonBipartite => 0,
+    };
+    let mut parents: Vec<usize> = (0..offset + cols).collect();
+
+    allowed
+        .indexed_iter()
+        .filter(|(_, &allowed)| allowed)
+        .for_each(|((a, b), _)| union(&mut parents, a, b + offset));
+
+    // Ceremonies tie together every seat that may or may not light up. Seats that never light up, or that are certain to
+    // light up because nobody else is left for that row, do not tie anything together.
+    let certain = |a: usize| allowed.row(a).iter().filter(|&&b| b).count() == 1;
+    let mut linked = vec![];
+    for (strip, beams) in ceremonies {
+        let (lit, seats): (Vec<usize>, Vec<usize>) = (0..rows)
+            .filter(|&a| strip[a] != NO_MATCH && allowed[(a, strip[a])])
+            .partition(|&a| certain(a));
+        let beams = beams.checked_sub(lit.len())?;
+        match seats.first() {
+            Some(&first) => {
+                seats.iter().for_each(|&a| union(&mut parents, first, a));
+                linked.push((first, strip, beams, lit));
+            }
+            None if beams > 0 => return None,
+            None => (),
+        }
+    }
+
+    let mut components: HashMap<usize, Component> = HashMap::new();
+    for a in 0..rows {
+        let root = find(&mut parents, a);
+        components.entry(root).or_insert_with(Component::new).rows.push(a);
+    }
+    for b in 0..cols {
+        let root = find(&mut parents, b + offset);
+        components.entry(root).or_insert_with(Component::new).cols.push(b);
+    }
+
+    for (first, strip, beams, lit) in linked {
+        let root = find(&mut parents, first);
+        let component = components.get_mut(&root)?;
+        let local = |b: usize| component.cols.binary_search(&b).ok();
+        let strip = component
+            .rows
+            .iter()
+            .map(|&a| match allowed.get((a, strip[a])) {
+                Some(true) if !lit.contains(&a) => local(strip[a]).unwrap_or(NO_MATCH),
+                _ => NO_MATCH,
+            })
+            .collect();
+        component.ceremonies.push((strip, beams));
+    }
+
+    // A component can only be completed if every row and every column can be matched within it.
+    let capacity = 1 + (rows > cols) as usize;
+    let components: Vec<Component> = components.into_values().sorted_by_key(|c| c.rows.first().copied()).collect();
+    let feasible = components.iter().all(|c| match kind {
+        SeasonKind::Bipartite => c.cols.len() <= c.rows.len() && c.rows.len() <= capacity * c.cols.len(),
+        SeasonKind::NonBipartite => c.rows.len().is_multiple_of(2),
+    });
+
+    feasible.then_some(components)
+}
+
+/// Finds the representative of a contestant's component, compressing the path to it along the way.
+pub(super) fn find(parents: &mut [usize], a: usize) -> usize {
+    let mut root = a;
+    while parents[root] != root {
+        root = parents[root];
+    }
+
+    let mut a = a;
+    while parents[a] != root {
+        (a, parents[a]) = (parents[a], root);
+    }
+    root
+}
+
+/// Merges the components of two contestants.
+pub(super) fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    parents[a.max(b)] = a.min(b);
+}
+
+impl Component {
+    /// Creates an empty component.
+    fn new() -> Component {
+        Component {
+            rows: vec![],
+            cols: vec![],
+            ceremonies: vec![],
+        }
+    }
+
+    /// Counts the worlds of this component, in total and per couple, indexed by its own rows and columns.
+    fn count(&self, kind: SeasonKind, allowed: &Array2<bool>) -> Result<(u64, Array2<u64>)> {
+        let allowed = allowed.select(Axis(0), &self.rows).select(Axis(1), &self.cols);
+        let counted = match kind {
+            SeasonKind::Bipartite => permanent::count_bipartite(&allowed),
+            SeasonKind::NonBipartite => permanent::count_pool(&allowed),
+        };
+
+        // Without any ceremonies, the dynamic program is exact; otherwise, it still bounds the number of worlds.
+        match (counted, self.ceremonies.is_empty()) {
+            (Some(counted), true) => return Ok(counted),
+            (Some((total, _)), false) if total > MAX_COMPONENT_WORLDS as u64 => {
+                return halves::count(kind, &allowed, &self.ceremonies)
+            }
+            _ => (),
+        };
+
+        let mut enumerator = Enumerator::new(kind, self.rows.len(), self.cols.len());
+        allowed
+            .indexed_iter()
+            .filter(|&((a, b), &allowed)| !allowed && (kind == SeasonKind::Bipartite || a < b))
+            .for_each(|((a, b), _)| {
+                enumerator.constrain(&Evidence::Truth((a, b), false));
+            });
+        self.ceremonies.iter().for_each(|(strip, beams)| {
+            enumerator.constrain(&Evidence::Ceremony(strip.clone(), *beams));
+        });
+
+        let Some(view) = enumerator.enumerate_up_to(MAX_COMPONENT_WORLDS) else {
+            return halves::count(kind, &allowed, &self.ceremonies);
+        };
+        let mut counts = Array2::zeros(allowed.dim());
+        view.iter().for_each(|world| {
+            world.iter().enumerate().for_each(|(a, &b)| counts[(a, b as usize)] += 1);
+        });
+
+        Ok((view.len() as u64, counts))
+    }
+}
@@ -1,53 +1,99 @@
 use crate::prelude::*;
 
-impl<'a> Season<'a> {
-    /// Creates a new Season with the given contestants.
-    pub fn new(m: &Vec<&str>, f: &Vec<&str>, worldview: &'a Vec<Vec<usize>>) -> Result<Season<'a>> {
+impl Season {
+    /// Creates a new Season with the given contestants, whose worlds have not been materialised yet.
+    ///
+    /// The groups may differ in size, in which case some contestants in the smaller group have two perfect matches.
+    /// The larger group always makes up the rows of the season, so a world maps each row onto a column.
+    pub fn new(m: &[&str], f: &[&str]) -> Result<Season> {
+        let (m, f) = if m.len() >= f.len() { (m, f) } else { (f, m) };
         let n = Season::check_lengths(m, f)?;
         let (ms, fs) = Season::create_maps_for(m, f);
-        let distribution = Season::create_distribution(n);
-        let worlds = (0..n.factorial()).collect();
+        let mut season = Season {
+            kind: SeasonKind::Bipartite,
+            m: m.iter().map(|&s| s.into()).collect(),
+            f: f.iter().map(|&s| s.into()).collect(),
+            ms,
+            fs,
+            n,
+            turn: 1,
+            found: 0,
+            engine: None,
+            total: 0,
+            counts: Array2::zeros((m.len(), n)),
+            evidence: vec![],
+            sampling: None,
+            worlds: Bitset::new(0),
+            worldview: None,
+        };
+
+        season.refresh()?;
+        Ok(season)
+    }
 
-        Ok(Season {
-            m: m.into_iter().map(|&s| s.into()).collect(),
-            f: f.into_iter().map(|&s| s.into()).collect(),
+    /// Creates a new Season over a single pool of contestants, any two of whom could be a perfect match.
+    ///
+    /// As with `Season::new`, its worlds have not been materialised yet.
+    pub fn new_nonbipartite(pool: &[&str]) -> Result<Season> {
+        let n = Season::check_pool(pool)?;
+        let (ms, fs) = Season::create_maps_for(pool, pool);
+        let mut season = Season {
+            kind: SeasonKind::NonBipartite,
+            m: pool.iter().map(|&s| s.into()).collect(),
+            f: pool.iter().map(|&s| s.into()).collect(),
             ms,
             fs,
             n,
             turn: 1,
             found: 0,
-            distribution,
-            worlds,
-            worldview,
-        })
+            engine: None,
+            total: 0,
+            counts: Array2::zeros((pool.len(), pool.len())),
+            evidence: vec![],
+            sampling: None,
+            worlds: Bitset::new(0),
+            worldview: None,
+        };
+
+        season.refresh()?;
+        Ok(season)
     }
 
-    /// Returns the size of the season (in terms of contestants per gender), provided the two genders are of equal size.
-    fn check_lengths(m: &Vec<&str>, f: &Vec<&str>) -> Result<usize> {
+    /// Returns the size of the season (in terms of couples per ceremony), provided that nobody in the smaller group
+    /// would need more than two matches.
+    fn check_lengths(m: &[&str], f: &[&str]) -> Result<usize> {
         let (lhs, rhs) = (m.len(), f.len());
-        match lhs == rhs {
-            true => Ok(lhs),
+        if lhs >= worldview::PACKED_NO_MATCH as usize {
+            return Err(anyhow!("Too many contestants to pack into a world: {lhs}"));
+        }
+
+        match rhs > 0 && lhs <= 2 * rhs {
+            true => Ok(rhs),
             _ => Err(anyhow!(
-                "Mismatched number of males and females: {} != {}",
-                lhs,
+                "Mismatched number of contestants: {} extra contestants for a group of {}",
+                lhs - rhs,
                 rhs
             )),
         }
     }
 
-    /// Creates a uniform pairwise distribution for a bipartite matching over two sets of n elements each.
-    fn create_distribution(n: usize) -> Array2<f32> {
-        let initializer = (n as f32).powi(-1);
-        Array2::from_elem((n, n), initializer)
+    /// Returns the size of a season over a single pool (in terms of couples per ceremony), provided that everyone can
+    /// be paired off.
+    fn check_pool(pool: &[&str]) -> Result<usize> {
+        if pool.len() >= worldview::PACKED_NO_MATCH as usize {
+            return Err(anyhow!("Too many contestants to pack into a world: {}", pool.len()));
+        }
+
+        match !pool.is_empty() && pool.len().is_multiple_of(2) {
+            true => Ok(pool.len() / 2),
+            _ => Err(anyhow!("Cannot pair off a pool of {} contestants", pool.len())),
+        }
     }
 
     /// Creates name-to-index maps to convert at every public callsite so that all internal operations and state can be represented numerically.
-    fn create_maps_for(
-        m: &Vec<&str>,
-        f: &Vec<&str>,
-    ) -> (HashMap<String, usize>, HashMap<String, usize>) {
-        let ms = HashMap::from_iter(m.into_iter().enumerate().map(|(i, &name)| (name.into(), i)));
-        let fs = HashMap::from_iter(f.into_iter().enumerate().map(|(i, &name)| (name.into(), i)));
+    fn create_maps_for(m: &[&str], f: &[&str]) -> (HashMap<String, usize>, HashMap<String, usize>) {
+        let ms = HashMap::from_iter(m.iter().enumerate().map(|(i, &name)| (name.into(), i)));
+        let fs = HashMap::from_iter(f.iter().enumerate().map(|(i, &name)| (name.into(), i)));
         (ms, fs)
     }
 }
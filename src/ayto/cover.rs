@@ -0,0 +1,183 @@
+use crate::prelude::*;
+
+/// The most remaining worlds that a truth cover is searched for over.
+const MAX_COVER_WORLDS: usize = 1_000;
+
+/// The most states that the search for the smallest fixed set of truth booths visits, before it settles for the
+/// smallest set that it has found so far.
+const MAX_COVER_NODES: usize = 10_000;
+
+impl Season {
+    /// Finds the fewest truth booths that always identify the perfect line-up among the remaining worlds.
+    ///
+    /// Each truth booth can depend on the outcomes of the ones before it, and that sequence is solved exactly like
+    /// `solve`, with only truth booths as moves. A fixed set of truth booths identifies the perfect line-up if every two
+    /// remaining worlds disagree on at least one of its couples; a greedy set comes first, and then a set one smaller is
+    /// searched for exactly, over and over, until there is none or the search runs out of states to visit. If solving
+    /// the sequence gives up, the fixed set is still searched for, down to the fewest truth booths that could ever tell
+    /// that many worlds apart.
+    pub fn truth_cover(&self) -> Result<TruthCover> {
+        let view = self.view()?;
+        if self.sampling.is_some() {
+            return Err(anyhow!("cannot cover sampled worlds; use `materialise` first"));
+        }
+        if self.num_worlds() > MAX_COVER_WORLDS {
+            return Err(anyhow!("can only cover up to {MAX_COVER_WORLDS} remaining worlds with truth booths"));
+        }
+
+        let mut game = self.truth_game(view);
+        let state: Vec<u32> = (0..self.num_worlds() as u32).collect();
+        let solved = game
+            .worst(&state)
+            .and_then(|(adaptive, _)| Ok((adaptive, self.decide_exactly(&mut game, &state, usize::MAX)?)));
+        let (adaptive, tree) = match solved {
+            Ok((adaptive, tree)) => (Some(adaptive), Some(tree)),
+            Err(e) => {
+                debug!("{e}");
+                (None, None)
+            }
+        };
+
+        // Every truth booth at most halves the remaining worlds, so none of them can do with fewer than this.
+        let fewest = adaptive.unwrap_or(state.len().next_power_of_two().trailing_zeros() as usize);
+
+        // A fixed set of truth booths is just a sequence that ignores their outcomes, so it is never any shorter.
+        let worlds = self.worlds.iter().map(|k| unsafe { view.get_unchecked(k) }).collect_vec();
+        let mut couples = self.cover_greedily(&worlds, vec![state.clone()]);
+        let mut budget = MAX_COVER_NODES;
+        while couples.len() > fewest {
+            let mut cover = vec![];
+            if !self.separate(&worlds, vec![state.clone()], couples.len() - 1, &mut cover, &mut vec![], &mut budget) {
+                break;
+            }
+            couples = cover;
+        }
+        let minimal = budget > 0 || couples.len() == fewest;
+        debug!("searched {} states for a fixed set of truth booths", MAX_COVER_NODES - budget);
+
+        Ok(TruthCover {
+            couples: couples
+                .into_iter()
+                .sorted()
+                .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
+                .collect(),
+            minimal,
+            adaptive,
+            tree,
+        })
+    }
+
+    /// Covers some parts greedily, with the truth booth that splits them most evenly at every step.
+    fn cover_greedily(&self, worlds: &[&[u8]], mut parts: Vec<Vec<u32>>) -> Vec<Couple> {
+        let candidates = self.couples();
+        let mut couples = vec![];
+        while parts.iter().any(|part| part.len() > 1) {
+            // Every two remaining worlds disagree on some couple, so the best one always tells some of them apart.
+            let Some(couple) = candidates
+                .iter()
+                .copied()
+                .min_by_key(|&couple| split(worlds, &parts, couple).iter().map(|part| part.len().pow(2)).sum::<usize>())
+            else {
+                break;
+            };
+            parts = split(worlds, &parts, couple);
+            couples.push(couple);
+        }
+
+        couples
+    }
+
+    /// Determines whether the worlds in some parts can all be told apart by at most `size` more truth booths, none of
+    /// them on a banned couple, and if so, adds the couples that do to `couples`.
+    ///
+    /// Worlds in different parts have already been told apart, and worlds in the same part agree on every couple so far.
+    /// Any two worlds in the same part need some couple that they disagree on, so the search only ever branches over the
+    /// couples of the pair that disagree on the fewest. Once a couple has been tried and failed, it is banned from every
+    /// branch after it, which would only find the same sets again in another order. Every state that the search visits
+    /// uses up some of the budget, and once it runs out, the search fails.
+    fn separate(
+        &self,
+        worlds: &[&[u8]],
+        mut parts: Vec<Vec<u32>>,
+        size: usize,
+        couples: &mut Vec<Couple>,
+        banned: &mut Vec<Couple>,
+        budget: &mut usize,
+    ) -> bool {
+        parts.retain(|part| part.len() > 1);
+        if parts.is_empty() {
+            return true;
+        }
+        if *budget == 0 {
+            return false;
+        }
+        *budget -= 1;
+
+        // Each truth booth at most halves a part, so there is no point in going on with parts too large to split in time.
+        let largest = parts.iter().map(|part| part.len()).max().unwrap_or(0);
+        if size == 0 || size < usize::BITS as usize && largest > 1 << size {
+            return false;
+        }
+
+        let disagree = |a: u32, b: u32| {
+            let (a, b) = (worlds[a as usize], worlds[b as usize]);
+            (0..a.len())
+                .filter(move |&m| a[m] != b[m])
+                .flat_map(move |m| [(m, a[m] as usize), (m, b[m] as usize)])
+                .map(|couple| self.canonical(couple))
+                .filter(|couple| !banned.contains(couple))
+                .unique()
+                .collect_vec()
+        };
+        let pairs = parts
+            .iter()
+            .flat_map(|part| part.iter().copied().tuple_combinations())
+            .map(|(a, b)| disagree(a, b))
+            .sorted_by_key(|couples| couples.len())
+            .collect_vec();
+        if pairs[0].is_empty() {
+            return false;
+        }
+
+        // Pairs of worlds that disagree on none of the same couples need a truth booth each.
+        let mut taken = vec![false; self.m.len() * self.f.len()];
+        let disjoint = pairs
+            .iter()
+            .filter(|couples| {
+                let free = couples.iter().all(|&(m, f)| !taken[m * self.f.len() + f]);
+                if free {
+                    couples.iter().for_each(|&(m, f)| taken[m * self.f.len() + f] = true);
+                }
+                free
+            })
+            .count();
+        if disjoint > size {
+            return false;
+        }
+
+        let unbanned = banned.len();
+        for &couple in &pairs[0] {
+            couples.push(couple);
+            if self.separate(worlds, split(worlds, &parts, couple), size - 1, couples, banned, budget) {
+                return true;
+            }
+            couples.pop();
+            banned.push(couple);
+        }
+
+        banned.truncate(unbanned);
+        false
+    }
+}
+
+/// Splits every part by whether its worlds match a couple.
+fn split(worlds: &[&[u8]], parts: &[Vec<u32>], (m, f): Couple) -> Vec<Vec<u32>> {
+    parts
+        .iter()
+        .flat_map(|part| {
+            let (matched, unmatched) = part.iter().partition(|&&w| worlds[w as usize][m] as usize == f);
+            [matched, unmatched]
+        })
+        .filter(|part: &Vec<u32>| !part.is_empty())
+        .collect()
+}
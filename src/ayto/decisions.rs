@@ -0,0 +1,100 @@
+use super::game::{self, Game, Probe};
+use crate::prelude::*;
+
+impl Season {
+    /// Builds a decision tree from the current state, which recommends a move at every node and follows every outcome
+    /// of it that could happen, down to the given depth or until only one world remains.
+    ///
+    /// By default, the moves cycle through the given kinds, and each one is the greedy pick under the given objective,
+    /// just like in a lookahead. An exact tree plays whichever move identifies the perfect line-up in the fewest moves in
+    /// the worst case instead, as solved by `solve`, so it ignores the kinds of move, is only feasible for small seasons,
+    /// and gives up just like `solve` does.
+    pub fn decision_tree(&self, moves: &[Move], depth: usize, objective: Objective, exact: bool) -> Result<Decision> {
+        let view = self.view()?;
+        if self.sampling.is_some() {
+            return Err(anyhow!("cannot build a decision tree over sampled worlds; use `materialise` first"));
+        }
+
+        match exact {
+            true => {
+                if self.n > game::MAX_GAME_SIZE {
+                    return Err(anyhow!("can only solve seasons of up to {} couples exactly", game::MAX_GAME_SIZE));
+                }
+
+                let mut game = self.game(view);
+                let state: Vec<u32> = (0..self.num_worlds() as u32).collect();
+                self.decide_exactly(&mut game, &state, depth)
+            }
+            _ => self.decide(moves, 0, depth, objective),
+        }
+    }
+
+    /// Builds the node of a greedy decision tree for the current state, where `turn` counts the moves before it.
+    fn decide(&self, moves: &[Move], turn: usize, depth: usize, objective: Objective) -> Result<Decision> {
+        let worlds = self.num_worlds();
+        let mut decision = Decision { worlds, solution: None, step: None, branches: vec![] };
+        if worlds == 1 {
+            decision.solution = self.worlds()?.pop();
+        }
+
+        let Some(&kind) = moves.get(turn % moves.len().max(1)) else {
+            return Ok(decision);
+        };
+        if worlds <= 1 || depth == 0 {
+            return Ok(decision);
+        }
+        let Some(couples) = self.greedy(kind, objective) else {
+            return Ok(decision);
+        };
+
+        for (outcome, branch) in self.outcomes(kind, &couples)? {
+            let next = branch.decide(moves, turn + 1, depth - 1, objective)?;
+            decision.branches.push(Branch { outcome, decision: next });
+        }
+        decision.step = Some(Step {
+            kind,
+            couples: couples
+                .into_iter()
+                .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
+                .collect(),
+        });
+
+        Ok(decision)
+    }
+
+    /// Builds the node of an exact decision tree for a state of the game tree.
+    pub(super) fn decide_exactly(&self, game: &mut Game, state: &[u32], depth: usize) -> Result<Decision> {
+        let mut decision = Decision { worlds: state.len(), solution: None, step: None, branches: vec![] };
+        if let &[world] = state {
+            decision.solution = Some(
+                self.couples_of(game.world(world))
+                    .into_iter()
+                    .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
+                    .collect(),
+            );
+        }
+
+        if state.len() <= 1 || depth == 0 {
+            return Ok(decision);
+        }
+        let (_, Some(probe)) = game.worst(state)? else {
+            return Ok(decision);
+        };
+
+        // Outcomes are listed in the same order as the greedy tree lists them, with a match before no match.
+        let outcomes = match game.probe(probe) {
+            Probe::Truth(_) => vec![(1, Outcome::Match(true)), (0, Outcome::Match(false))],
+            Probe::Ceremony(_) => (0..=self.n).map(|beams| (beams, Outcome::Beams(beams))).collect(),
+        };
+        for (value, outcome) in outcomes {
+            let part = state.iter().copied().filter(|&w| game.outcome(w, probe) == value).collect_vec();
+            if !part.is_empty() {
+                let next = self.decide_exactly(game, &part, depth - 1)?;
+                decision.branches.push(Branch { outcome, decision: next });
+            }
+        }
+        decision.step = Some(self.step(game.probe(probe)));
+
+        Ok(decision)
+    }
+}
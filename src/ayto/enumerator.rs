@@ -0,0 +1,340 @@
+use crate::prelude::*;
+
+/// Enumerates only those worlds that are consistent with a season's evidence.
+///
+/// This backtracks over partial matchings, and prunes any branch that breaks a truth booth or that can no longer light
+/// up exactly the right number of beams at every ceremony.
+#[derive(Clone, Debug)]
+pub struct Enumerator {
+    kind: SeasonKind,
+    rows: usize,
+    cols: usize,
+    forbidden: Array2<bool>,
+    forced: Vec<usize>,
+    ceremonies: Vec<(Vec<usize>, usize)>,
+    contradiction: bool,
+}
+
+/// The state of a single branch of the search.
+#[derive(Clone, Debug)]
+struct Search {
+    /// The partial world, with `NO_MATCH` for everyone who has not been placed yet.
+    world: Vec<usize>,
+    /// The number of rows placed so far.
+    placed: usize,
+    /// The number of rows matched to each column so far.
+    uses: Vec<usize>,
+    /// The number of columns that have been matched twice so far.
+    doubles: usize,
+    /// The number of columns that have not been matched so far.
+    uncovered: usize,
+    /// The number of beams lit up so far at each ceremony.
+    hits: Vec<usize>,
+    /// The worlds found so far.
+    view: Worldview,
+    /// The number of worlds after which the search stops.
+    limit: usize,
+}
+
+impl Enumerator {
+    /// Creates an enumerator over every world of a season with no evidence.
+    pub fn new(kind: SeasonKind, rows: usize, cols: usize) -> Enumerator {
+        Enumerator {
+            kind,
+            rows,
+            cols,
+            forbidden: Array2::from_elem((rows, cols), false),
+            forced: vec![NO_MATCH; rows],
+            ceremonies: vec![],
+            contradiction: false,
+        }
+    }
+
+    /// Restricts the enumeration to worlds that are consistent with a piece of evidence.
+    pub fn constrain(&mut self, evidence: &Evidence) -> &mut Self {
+        match evidence {
+            Evidence::Truth((m, f), true) => {
+                self.force(*m, *f);
+                if self.kind == SeasonKind::NonBipartite {
+                    self.force(*f, *m);
+                }
+            }
+            Evidence::Truth((m, f), false) => {
+                self.forbidden[(*m, *f)] = true;
+                if self.kind == SeasonKind::NonBipartite {
+                    self.forbidden[(*f, *m)] = true;
+                }
+            }
+            Evidence::Ceremony(strip, beams) => {
+                self.ceremonies.push((strip.clone(), *beams));
+            }
+        };
+        self
+    }
+
+    /// Enumerates every consistent world into a new worldview, splitting the search across threads at its first choice.
+    ///
+    /// Without any evidence at all, every world is generated directly instead, which is much faster.
+    pub fn enumerate(&self) -> Worldview {
+        let unconstrained = self.ceremonies.is_empty()
+            && self.forced.iter().all(|&b| b == NO_MATCH)
+            && self.forbidden.iter().all(|&forbidden| !forbidden);
+        match (unconstrained, self.kind) {
+            (true, SeasonKind::Bipartite) if self.rows <= 20 => return worldview::generate_all(self.rows, self.cols),
+            (true, SeasonKind::NonBipartite) => return worldview::generate_matchings(self.rows),
+            _ => (),
+        }
+
+        let root = self.root(usize::MAX);
+        if self.contradiction || !self.feasible(&root) {
+            return root.view;
+        }
+
+        let Some(a) = self.next(&root) else {
+            return root.view;
+        };
+
+        let branches: Vec<Worldview> = self
+            .options(a)
+            .into_par_iter()
+            .map(|b| {
+                let mut search = root.clone();
+                if self.allowed(&search, a, b) {
+                    self.place(&mut search, a, b);
+                    if self.feasible(&search) {
+                        self.walk(&mut search);
+                    }
+                }
+                search.view
+            })
+            .collect();
+
+        branches.into_iter().fold(root.view, |mut view, branch| {
+            view.append(branch);
+            view
+        })
+    }
+
+    /// Enumerates every consistent world into a new worldview on a single thread, unless there are more than `limit`.
+    pub fn enumerate_up_to(&self, limit: usize) -> Option<Worldview> {
+        let mut search = self.root(limit.saturating_add(1));
+        if !self.contradiction && self.feasible(&search) {
+            self.walk(&mut search);
+        }
+
+        (search.view.len() <= limit).then_some(search.view)
+    }
+
+    /// Finds the first consistent world, if there is one, without enumerating any of the others.
+    pub fn first(&self) -> Option<Vec<usize>> {
+        let mut search = self.root(1);
+        if self.contradiction || !self.feasible(&search) {
+            return None;
+        }
+
+        self.walk(&mut search);
+        let world = search.view.iter().next().map(|world| world.iter().map(|&b| b as usize).collect());
+        world
+    }
+
+    /// Returns every ceremony that constrains the enumeration, as a match strip and its number of beams.
+    pub fn ceremonies(&self) -> &[(Vec<usize>, usize)] {
+        &self.ceremonies
+    }
+
+    /// Returns the couples that the truth booths still allow, ignoring every ceremony.
+    ///
+    /// A forced couple rules out every other match for its row, and for its column too, unless that column could be
+    /// matched twice.
+    pub fn allowed_couples(&self) -> Array2<bool> {
+        let mut taken = vec![false; self.cols];
+        self.forced
+            .iter()
+            .filter(|&&b| b != NO_MATCH && self.rows == self.cols)
+            .for_each(|&b| taken[b] = true);
+
+        Array2::from_shape_fn((self.rows, self.cols), |(a, b)| {
+            !self.contradiction
+                && !self.forbidden[(a, b)]
+                && [NO_MATCH, b].contains(&self.forced[a])
+                && (self.forced[a] == b || !taken[b])
+                && (self.kind == SeasonKind::Bipartite || a != b)
+        })
+    }
+
+    /// Creates the state of a search that has not placed anybody yet, and that stops after `limit` worlds.
+    fn root(&self, limit: usize) -> Search {
+        Search {
+            world: vec![NO_MATCH; self.rows],
+            placed: 0,
+            uses: vec![0; self.cols],
+            doubles: 0,
+            uncovered: self.cols,
+            hits: vec![0; self.ceremonies.len()],
+            view: Worldview::new(self.rows),
+            limit,
+        }
+    }
+
+    /// Records that a couple must be a perfect match, noting any contradiction with an earlier truth booth.
+    fn force(&mut self, m: usize, f: usize) {
+        match self.forced[m] {
+            NO_MATCH => self.forced[m] = f,
+            g if g != f => self.contradiction = true,
+            _ => (),
+        }
+    }
+
+    /// Picks the next contestant to place, if there is one.
+    ///
+    /// Rows with a forced match are always placed first, so that their matches are reserved before anybody else
+    /// competes for them.
+    fn next(&self, search: &Search) -> Option<usize> {
+        match self.kind {
+            SeasonKind::Bipartite => (0..self.rows)
+                .filter(|&m| search.world[m] == NO_MATCH)
+                .min_by_key(|&m| self.forced[m] == NO_MATCH),
+            SeasonKind::NonBipartite => search.world.iter().position(|&j| j == NO_MATCH),
+        }
+    }
+
+    /// Lists every possible match for a contestant, before checking them against the state of the search.
+    fn options(&self, a: usize) -> Vec<usize> {
+        match (self.forced[a], self.kind) {
+            (NO_MATCH, SeasonKind::Bipartite) => (0..self.cols).collect(),
+            (NO_MATCH, SeasonKind::NonBipartite) => ((a + 1)..self.rows).collect(),
+            (b, _) => vec![b],
+        }
+    }
+
+    /// Determines whether a couple can be placed in the current state of the search.
+    fn allowed(&self, search: &Search, a: usize, b: usize) -> bool {
+        if self.forbidden[(a, b)] {
+            return false;
+        }
+
+        match self.kind {
+            SeasonKind::Bipartite => match search.uses[b] {
+                0 => true,
+                1 => search.doubles < self.rows - self.cols,
+                _ => false,
+            },
+            SeasonKind::NonBipartite => {
+                a != b && search.world[b] == NO_MATCH && [NO_MATCH, a].contains(&self.forced[b])
+            }
+        }
+    }
+
+    /// Places a couple into the search.
+    fn place(&self, search: &mut Search, a: usize, b: usize) {
+        search.world[a] = b;
+        match self.kind {
+            SeasonKind::Bipartite => {
+                search.placed += 1;
+                search.uses[b] += 1;
+                match search.uses[b] {
+                    1 => search.uncovered -= 1,
+                    _ => search.doubles += 1,
+                }
+            }
+            SeasonKind::NonBipartite => {
+                search.world[b] = a;
+                search.placed += 2;
+            }
+        }
+
+        self.ceremonies
+            .iter()
+            .zip(search.hits.iter_mut())
+            .for_each(|((strip, _), hits)| *hits += self.lit(strip, a, b) as usize);
+    }
+
+    /// Takes a couple back out of the search.
+    fn unplace(&self, search: &mut Search, a: usize, b: usize) {
+        self.ceremonies
+            .iter()
+            .zip(search.hits.iter_mut())
+            .for_each(|((strip, _), hits)| *hits -= self.lit(strip, a, b) as usize);
+
+        search.world[a] = NO_MATCH;
+        match self.kind {
+            SeasonKind::Bipartite => {
+                search.placed -= 1;
+                match search.uses[b] {
+                    1 => search.uncovered += 1,
+                    _ => search.doubles -= 1,
+                }
+                search.uses[b] -= 1;
+            }
+            SeasonKind::NonBipartite => {
+                search.world[b] = NO_MATCH;
+                search.placed -= 2;
+            }
+        }
+    }
+
+    /// Determines whether placing a couple lights up a beam for a ceremony.
+    ///
+    /// In a single pool, ceremonies record each couple against its lower id.
+    fn lit(&self, strip: &[usize], a: usize, b: usize) -> bool {
+        match self.kind {
+            SeasonKind::Bipartite => strip[a] == b,
+            SeasonKind::NonBipartite => strip[a.min(b)] == a.max(b),
+        }
+    }
+
+    /// Determines whether the search could still complete into a consistent world.
+    ///
+    /// Every column must eventually be matched, and every ceremony must be able to reach its number of beams without
+    /// having already passed it.
+    fn feasible(&self, search: &Search) -> bool {
+        if self.kind == SeasonKind::Bipartite && search.uncovered > self.rows - search.placed {
+            return false;
+        }
+
+        self.ceremonies
+            .iter()
+            .zip(&search.hits)
+            .all(|((strip, beams), &hits)| {
+                hits <= *beams && hits + self.potential(search, strip) >= *beams
+            })
+    }
+
+    /// Counts the seats at a ceremony that could still light up a beam, given the contestants placed so far.
+    fn potential(&self, search: &Search, strip: &[usize]) -> usize {
+        strip
+            .iter()
+            .enumerate()
+            .filter(|&(a, &b)| {
+                b != NO_MATCH
+                    && search.world[a] == NO_MATCH
+                    && [NO_MATCH, b].contains(&self.forced[a])
+                    && self.allowed(search, a, b)
+            })
+            .count()
+    }
+
+    /// Walks every branch below the current state of the search, collecting each complete world.
+    fn walk(&self, search: &mut Search) {
+        let Some(a) = self.next(search) else {
+            search.view.push(&search.world);
+            return;
+        };
+
+        for b in self.options(a) {
+            if search.view.len() >= search.limit {
+                return;
+            }
+
+            if !self.allowed(search, a, b) {
+                continue;
+            }
+
+            self.place(search, a, b);
+            if self.feasible(search) {
+                self.walk(search);
+            }
+            self.unplace(search, a, b);
+        }
+    }
+}
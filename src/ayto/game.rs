@@ -0,0 +1,358 @@
+use std::collections::HashSet;
+
+use super::symmetry;
+use crate::prelude::*;
+
+/// The largest season whose game tree is solved exactly.
+pub(super) const MAX_GAME_SIZE: usize = 6;
+
+/// The most states that a game tree searches, before it gives up.
+const MAX_GAME_STATES: usize = 100_000;
+
+/// A move that the game tree can play.
+#[derive(PartialEq, Eq, Hash)]
+pub(super) enum Probe {
+    /// A couple is sent to the truth booth, which splits the worlds by whether they match them.
+    Truth(Couple),
+    /// A packed match strip is sat at a ceremony, which splits the worlds by how many beams they light up.
+    Ceremony(Vec<u8>),
+}
+
+/// The game tree over the remaining worlds of a season, along with every state that has been solved so far.
+///
+/// A state is the sorted list of the worlds that are still consistent with every outcome so far, by their position in
+/// `worlds`. Every move is worth the same, whether it is a truth booth or a ceremony.
+pub(super) struct Game<'a> {
+    /// The season that the game is played over.
+    season: &'a Season,
+    /// The remaining worlds of the season when the game was set up.
+    worlds: Vec<&'a [u8]>,
+    /// Every move that can be played, which is every couple and every line-up.
+    probes: Vec<Probe>,
+    /// The number of couples, so that no ceremony lights up more than this many beams.
+    n: usize,
+    /// The fewest moves that are known to identify each state in the worst case, and a first move that does.
+    solved: HashMap<Vec<u32>, (usize, Option<usize>)>,
+    /// The most moves that are known not to be enough for each state in the worst case.
+    failed: HashMap<Vec<u32>, usize>,
+    /// The fewest moves that identify each state, summed over its worlds, and a first move that does.
+    totals: HashMap<Vec<u32>, (usize, Option<usize>)>,
+    /// The fewest moves that each state is known to need at least, summed over its worlds, for states that have only been
+    /// solved far enough to tell that they cannot beat some budget.
+    floors: HashMap<Vec<u32>, usize>,
+    /// The number of states that can still be searched, before the game gives up.
+    states: usize,
+}
+
+impl Season {
+    /// Solves the game from the current state exactly, like Knuth's analysis of Mastermind.
+    ///
+    /// The perfect line-up counts as identified once only one world remains, and any move can be played at any time, so
+    /// this is the number of truth booths and ceremonies that it takes to get there when playing perfectly, both in the
+    /// worst case and on average over the remaining worlds. Every couple and every line-up is tried at every state, even
+    /// if it has already been ruled out, so this is only feasible for small seasons, and it gives up once it has searched
+    /// too many states.
+    pub fn solve(&self) -> Result<Solution> {
+        if self.n > MAX_GAME_SIZE {
+            return Err(anyhow!("can only solve seasons of up to {MAX_GAME_SIZE} couples exactly"));
+        }
+        if self.sampling.is_some() {
+            return Err(anyhow!("cannot solve sampled worlds exactly; use `materialise` first"));
+        }
+
+        let view = self.view()?;
+        let mut game = self.game(view);
+        let state: Vec<u32> = (0..game.worlds.len() as u32).collect();
+        let (worst, worst_probe) = game.worst(&state)?;
+        let (total, expected_probe) = game.total(&state, usize::MAX, self.n + 1);
+        game.check()?;
+        let expected = total as f64 / state.len().max(1) as f64;
+        debug!("solved {} states in the worst case and {} on average", game.solved.len(), game.totals.len());
+
+        Ok(Solution {
+            worst,
+            worst_step: worst_probe.map(|probe| self.step(&game.probes[probe])),
+            expected,
+            expected_step: expected_probe.map(|probe| self.step(&game.probes[probe])),
+        })
+    }
+
+    /// Sets up the game tree over the remaining worlds of a materialised season.
+    pub(super) fn game<'a>(&'a self, view: &'a Worldview) -> Game<'a> {
+        // Line-ups are the ceremonies of every world at all, which can repeat when some contestant has two matches.
+        let lineups = Enumerator::new(self.kind, self.m.len(), self.f.len()).enumerate();
+        let ceremonies: HashSet<Vec<u8>> = lineups
+            .iter()
+            .map(|world| worldview::pack(&self.ceremony_of(world)))
+            .collect();
+
+        let probes = self
+            .couples()
+            .into_iter()
+            .map(Probe::Truth)
+            .chain(ceremonies.into_iter().sorted().map(Probe::Ceremony))
+            .collect();
+
+        self.game_of(view, probes)
+    }
+
+    /// Sets up the game tree over the remaining worlds of a materialised season, where only truth booths can be played.
+    ///
+    /// There are only as many truth booths as couples, so unlike the full game, this works for seasons of any size.
+    pub(super) fn truth_game<'a>(&'a self, view: &'a Worldview) -> Game<'a> {
+        let probes = self.couples().into_iter().map(Probe::Truth).collect();
+        self.game_of(view, probes)
+    }
+
+    /// Sets up the game tree over the remaining worlds of a materialised season, with the given moves.
+    fn game_of<'a>(&'a self, view: &'a Worldview, probes: Vec<Probe>) -> Game<'a> {
+        let worlds = self.worlds.iter().map(|k| unsafe { view.get_unchecked(k) }).collect_vec();
+        Game {
+            season: self,
+            worlds,
+            probes,
+            n: self.n,
+            solved: HashMap::new(),
+            failed: HashMap::new(),
+            totals: HashMap::new(),
+            floors: HashMap::new(),
+            states: MAX_GAME_STATES,
+        }
+    }
+
+    /// Converts a move of the game tree back into the couples that it involves.
+    pub(super) fn step(&self, probe: &Probe) -> Step {
+        let (kind, couples) = match probe {
+            &Probe::Truth(couple) => (Move::Truth, vec![couple]),
+            Probe::Ceremony(strip) => (Move::Ceremony, self.strip_of(&worldview::unpack(strip))),
+        };
+
+        Step {
+            kind,
+            couples: couples
+                .into_iter()
+                .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
+                .collect(),
+        }
+    }
+}
+
+impl Game<'_> {
+    /// Fails once the game has searched as many states as it can, since nothing that it solved after that can be trusted.
+    pub(super) fn check(&self) -> Result<()> {
+        match self.states {
+            0 => Err(anyhow!("gave up after searching {MAX_GAME_STATES} states; try again once fewer worlds remain")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts one more state as searched, and determines whether the game can still go on.
+    fn spend(&mut self) -> bool {
+        self.states = self.states.saturating_sub(1);
+        self.states > 0
+    }
+
+    /// Returns one of the remaining worlds of the season, by its position in the game.
+    pub(super) fn world(&self, world: u32) -> &[u8] {
+        self.worlds[world as usize]
+    }
+
+    /// Returns one of the moves that the game can play.
+    pub(super) fn probe(&self, probe: usize) -> &Probe {
+        &self.probes[probe]
+    }
+
+    /// Determines the outcome of a move in a world, which is whether a truth booth matches or how many beams light up.
+    pub(super) fn outcome(&self, world: u32, probe: usize) -> usize {
+        let world = self.worlds[world as usize];
+        match &self.probes[probe] {
+            &Probe::Truth((m, f)) => (world[m] as usize == f) as usize,
+            Probe::Ceremony(strip) => worldview::beams(world, strip),
+        }
+    }
+
+    /// Lists the moves that tell some of the worlds in a state apart, skipping any that splits them just like an earlier
+    /// one, in order of how evenly they split them.
+    ///
+    /// Moves that some symmetry of the state sends onto each other play out the same way, so only the first move in each
+    /// orbit is listed.
+    fn moves(&self, state: &[u32]) -> Vec<(usize, Vec<Vec<u32>>)> {
+        let orbit = self.orbits(state);
+        let mut seen = HashSet::new();
+        (0..self.probes.len())
+            .filter(|&probe| orbit[probe] == probe)
+            .filter_map(|probe| {
+                // Two moves split a state the same way if its worlds fall into their parts in the same order.
+                let mut labels = vec![u8::MAX; self.n + 1];
+                let mut next = 0;
+                let key = state
+                    .iter()
+                    .map(|&w| {
+                        let outcome = &mut labels[self.outcome(w, probe)];
+                        if *outcome == u8::MAX {
+                            (*outcome, next) = (next, next + 1);
+                        }
+                        *outcome
+                    })
+                    .collect_vec();
+                if next < 2 || seen.contains(&key) {
+                    return None;
+                }
+
+                let mut parts = vec![vec![]; next as usize];
+                state.iter().zip(&key).for_each(|(&w, &part)| parts[part as usize].push(w));
+                seen.insert(key);
+                Some((probe, parts))
+            })
+            .sorted_by_cached_key(|(_, parts)| parts.iter().map(|part| part.len().pow(2)).sum::<usize>())
+            .collect()
+    }
+
+    /// Maps every move to the first move in its orbit under the relabelings that leave a state as it is.
+    fn orbits(&self, state: &[u32]) -> Vec<usize> {
+        let season = self.season;
+        let worlds: HashSet<&[u8]> = state.iter().map(|&w| self.worlds[w as usize]).collect();
+        let generators = season.symmetries(|relabeling| {
+            worlds
+                .iter()
+                .all(|world| worlds.contains(season.relabel_world(world, relabeling).as_slice()))
+        });
+
+        symmetry::orbits(&self.probes, &generators, |probe, relabeling| match probe {
+            &Probe::Truth(couple) => Probe::Truth(season.relabel_couple(couple, relabeling)),
+            Probe::Ceremony(strip) => {
+                Probe::Ceremony(worldview::pack(&season.relabel_strip(&worldview::unpack(strip), relabeling)))
+            }
+        })
+    }
+
+    /// Solves a state in the worst case, by deepening the search one move at a time until some move always identifies
+    /// the perfect line-up in time.
+    pub(super) fn worst(&mut self, state: &[u32]) -> Result<(usize, Option<usize>)> {
+        let mut depth = 0;
+        loop {
+            if let Some(probe) = self.within(state, depth, self.n + 1) {
+                self.check()?;
+                return Ok((depth, probe));
+            }
+            self.check()?;
+            depth += 1;
+        }
+    }
+
+    /// Determines whether a state can always be identified within some number of moves, and if so, with which first move.
+    ///
+    /// No move in a state has more outcomes than in any state before it, so `outcomes` is the most that a move had in
+    /// the state before this one.
+    fn within(&mut self, state: &[u32], depth: usize, outcomes: usize) -> Option<Option<usize>> {
+        if state.len() <= 1 {
+            return Some(None);
+        }
+
+        // There is no point in searching states that are too large to split into single worlds in time.
+        let fits = (0..depth).try_fold(1usize, |capacity, _| capacity.checked_mul(outcomes));
+        if fits.is_some_and(|capacity| state.len() > capacity) {
+            return None;
+        }
+
+        match (self.solved.get(state), self.failed.get(state)) {
+            (Some(&(solved, probe)), _) if solved <= depth => return Some(probe),
+            (_, Some(&failed)) if failed >= depth => return None,
+            _ => {}
+        }
+        if !self.spend() {
+            return None;
+        }
+
+        if depth > 0 {
+            let moves = self.moves(state);
+            let outcomes = moves.iter().map(|(_, parts)| parts.len()).max().unwrap_or(outcomes);
+            for (probe, parts) in moves {
+                if parts.iter().all(|part| self.within(part, depth - 1, outcomes).is_some()) {
+                    self.solved.insert(state.to_vec(), (depth, Some(probe)));
+                    return Some(Some(probe));
+                }
+            }
+        }
+
+        self.failed.insert(state.to_vec(), depth);
+        None
+    }
+
+    /// Solves a state on average, as the fewest moves that identify each of its worlds in total, so that every sum is
+    /// exact; dividing by the number of worlds gives the average.
+    ///
+    /// Every move is tried, but the rest of a move is skipped as soon as it cannot beat the best move so far, or the
+    /// budget. The total is exact if it is below the budget, and otherwise it is only a bound from below that is at least
+    /// the budget, which is all that a caller with that budget needs to know. Just like in `within`, `outcomes` is the
+    /// most outcomes that a move had in the state before this one.
+    pub(super) fn total(&mut self, state: &[u32], budget: usize, outcomes: usize) -> (usize, Option<usize>) {
+        if state.len() <= 1 {
+            return (0, None);
+        }
+        if let Some(&solved) = self.totals.get(state) {
+            return solved;
+        }
+        if let Some(&floor) = self.floors.get(state).filter(|&&floor| floor >= budget) {
+            return (floor, None);
+        }
+        if !self.spend() {
+            return (budget, None);
+        }
+
+        let moves = self.moves(state);
+        let outcomes = moves.iter().map(|(_, parts)| parts.len()).max().unwrap_or(outcomes);
+        let bound = |worlds: usize| bound(worlds, outcomes);
+
+        let mut best = (usize::MAX, None);
+        for (probe, parts) in moves {
+            // Every part still needs at least as many moves as its bound, so the sum of the bounds only ever grows.
+            let mut cost = state.len() + parts.iter().map(|part| bound(part.len())).sum::<usize>();
+            for part in &parts {
+                let limit = best.0.min(budget);
+                if cost >= limit {
+                    break;
+                }
+
+                // This part can only take up whatever is left of the limit once every other part takes its bound.
+                let bound = bound(part.len());
+                cost += self.total(part, limit - cost + bound, outcomes).0 - bound;
+            }
+
+            if cost < best.0 {
+                best = (cost, Some(probe));
+            }
+        }
+
+        match best.0 < budget {
+            true => {
+                self.totals.insert(state.to_vec(), best);
+            }
+            _ => {
+                self.floors.insert(state.to_vec(), best.0);
+            }
+        }
+        best
+    }
+}
+
+/// Bounds the number of moves that a state of the given size needs in total from below, given the most outcomes that
+/// any move can have in it.
+///
+/// Every world is a leaf of the tree of moves, and each move has at most `outcomes` children, so no tree has its leaves
+/// any shallower on average than a complete one, which fills up every level before it starts the next.
+fn bound(worlds: usize, outcomes: usize) -> usize {
+    if worlds <= 1 {
+        return 0;
+    }
+
+    let outcomes = outcomes.max(2);
+    let (mut depth, mut capacity) = (0, 1);
+    while capacity * outcomes <= worlds {
+        (depth, capacity) = (depth + 1, capacity * outcomes);
+    }
+
+    // Each leaf on the deepest full level that is split makes room for `outcomes - 1` more leaves on the next one.
+    let split = (worlds - capacity).div_ceil(outcomes - 1);
+    depth * (capacity - split) + (depth + 1) * (worlds - capacity + split)
+}
@@ -0,0 +1,475 @@
+use std::{
+    hash::{BuildHasherDefault, Hasher},
+    ops::Range,
+};
+
+use crate::prelude::*;
+
+/// The largest number of partial worlds that either half is allowed to walk through.
+const MAX_HALF_WORLDS: usize = 20_000_000;
+
+/// A map keyed by packed keys, which are hashed much faster than the default hasher would.
+type Keyed<V> = HashMap<u64, V, BuildHasherDefault<KeyHasher>>;
+
+/// The partial worlds of one half, tallied by the matches they use and then by the beams they light up at each ceremony.
+type Tally = Keyed<Keyed<u64>>;
+
+/// A multiplicative hasher, for the packed keys of a tally.
+#[derive(Default)]
+struct KeyHasher(u64);
+
+/// One half of a season, which places its own contestants and leaves everybody else to the other half.
+///
+/// In a bipartite season, each half places half of the rows, and notes how many times it has matched each column. In a
+/// single pool, the lower half pairs off its own contestants, possibly with contestants from the upper half, and the upper
+/// half pairs off whoever is left among its own contestants; both note which contestants from the upper half the lower
+/// half should take.
+struct Half<'a> {
+    kind: SeasonKind,
+    allowed: &'a Array2<bool>,
+    ceremonies: &'a [(Vec<usize>, usize)],
+    /// The contestants that this half places.
+    members: Range<usize>,
+    /// The first contestant of the upper half.
+    middle: usize,
+    /// The most beams that the other half could light up at each ceremony.
+    elsewhere: Vec<usize>,
+    /// The place value of each entry of a usage, when it is packed into a key.
+    places: Vec<u64>,
+    /// The number of bits that the beams of each ceremony take up, when they are packed into a key.
+    width: u32,
+}
+
+/// The state of a walk through the partial worlds of a half.
+#[derive(Clone)]
+struct Partial {
+    /// The couples placed so far.
+    couples: Vec<Couple>,
+    /// Whether each contestant has been placed so far, or left for the other half.
+    placed: Vec<bool>,
+    /// In a bipartite season, the number of times each column has been matched so far; in a single pool, whether each
+    /// contestant of the upper half has been taken by, or left open for, the lower half.
+    usage: Vec<u8>,
+    /// The number of beams lit up so far at each ceremony.
+    beams: Vec<u8>,
+    /// The number of seats at each ceremony that this half has not placed yet, but that could still light up a beam.
+    open: Vec<usize>,
+    /// The usage, packed into a key.
+    usage_key: u64,
+    /// The beams at every ceremony, packed into a key.
+    beams_key: u64,
+    /// The number of partial worlds walked through so far.
+    leaves: usize,
+}
+
+/// Counts the worlds of a season, in total and per couple, by meeting in the middle.
+///
+/// The season is split into two halves, which each walk through their own partial worlds and tally them by the matches
+/// that they use and by the beams that they light up. A partial world from each half makes up a whole world exactly when
+/// their matches complete each other and their beams add up to the beams of every ceremony, so each half only has to look
+/// up the tallies of the other. A second walk through each half then credits every couple with the number of worlds that
+/// its partial world completes into. Errors if either half has too many partial worlds, or if the counts could overflow.
+pub fn count(
+    kind: SeasonKind,
+    allowed: &Array2<bool>,
+    ceremonies: &[(Vec<usize>, usize)],
+) -> Result<(u64, Array2<u64>)> {
+    let (rows, cols) = allowed.dim();
+    let middle = rows / 2;
+    let halves = [
+        Half::new(kind, allowed, ceremonies, 0..middle, middle)?,
+        Half::new(kind, allowed, ceremonies, middle..rows, middle)?,
+    ];
+
+    let (lower, upper) = rayon::join(|| halves[0].tally(), || halves[1].tally());
+    let tallies = [lower?, upper?];
+    debug!("met in the middle with {} and {} keys", tallies[0].len(), tallies[1].len());
+
+    let weights = [
+        halves[0].weigh(&tallies[0], &halves[1], &tallies[1])?,
+        halves[1].weigh(&tallies[1], &halves[0], &tallies[0])?,
+    ];
+
+    let total = tallies[0]
+        .iter()
+        .flat_map(|(usage, beams)| beams.iter().map(move |(beams, &count)| (usage, beams, count)))
+        .try_fold(0u64, |total, (usage, beams, count)| {
+            total.checked_add(count.checked_mul(weights[0][usage][beams])?)
+        })
+        .context("too many worlds to count")?;
+
+    let mut counts = Array2::zeros((rows, cols));
+    for (half, weights) in halves.iter().zip(&weights) {
+        let branches = half.walk(
+            || Array2::<u64>::zeros((rows, cols)),
+            |counts, partial| {
+                let weight = weights
+                    .get(&partial.usage_key)
+                    .and_then(|beams| beams.get(&partial.beams_key))
+                    .copied()
+                    .unwrap_or(0);
+                for &(a, b) in &partial.couples {
+                    counts[(a, b)] += weight;
+                    if kind == SeasonKind::NonBipartite {
+                        counts[(b, a)] += weight;
+                    }
+                }
+            },
+        )?;
+        branches.iter().for_each(|branch| counts += branch);
+    }
+
+    Ok((total, counts))
+}
+
+impl<'a> Half<'a> {
+    /// Creates the half of a season that places the given contestants.
+    ///
+    /// Errors if its usages or beams cannot be packed into keys.
+    fn new(
+        kind: SeasonKind,
+        allowed: &'a Array2<bool>,
+        ceremonies: &'a [(Vec<usize>, usize)],
+        members: Range<usize>,
+        middle: usize,
+    ) -> Result<Half<'a>> {
+        let (rows, cols) = allowed.dim();
+        let (base, usage) = match kind {
+            SeasonKind::Bipartite => (2 + (rows > cols) as u64, cols),
+            SeasonKind::NonBipartite => (2, rows - middle),
+        };
+        let places: Vec<u64> = (0..usage)
+            .map(|i| base.checked_pow(i as u32))
+            .collect::<Option<_>>()
+            .filter(|_| base.checked_pow(usage as u32).is_some())
+            .context("too many contestants to meet in the middle")?;
+
+        let most = ceremonies.iter().map(|&(_, beams)| beams).max().unwrap_or(0);
+        let width = usize::BITS - most.leading_zeros();
+        if ceremonies.len() * width as usize > u64::BITS as usize {
+            return Err(anyhow!("too many ceremonies to meet in the middle"));
+        }
+
+        let mut half = Half {
+            kind,
+            allowed,
+            ceremonies,
+            members,
+            middle,
+            elsewhere: vec![],
+            places,
+            width,
+        };
+
+        half.elsewhere = ceremonies
+            .iter()
+            .map(|(strip, _)| (0..rows).filter(|a| !half.members.contains(a) && half.seat(strip, *a)).count())
+            .collect();
+        Ok(half)
+    }
+
+    /// Packs the beams of every ceremony into a key.
+    fn pack(&self, beams: impl Iterator<Item = usize>) -> u64 {
+        beams
+            .enumerate()
+            .map(|(c, beams)| (beams as u64) << (c as u32 * self.width))
+            .sum()
+    }
+
+    /// Determines whether a contestant's seat at a ceremony could light up a beam at all.
+    ///
+    /// In a single pool, ceremonies record each couple against its lower id, so each seat belongs to that contestant.
+    fn seat(&self, strip: &[usize], a: usize) -> bool {
+        strip[a] != NO_MATCH && self.allowed[(a, strip[a])]
+    }
+
+    /// Tallies the partial worlds of this half by the matches they use and the beams they light up.
+    fn tally(&self) -> Result<Tally> {
+        let branches = self.walk(Tally::default, |tally, partial| {
+            *tally
+                .entry(partial.usage_key)
+                .or_default()
+                .entry(partial.beams_key)
+                .or_default() += 1;
+        })?;
+
+        let mut tally = Tally::default();
+        for (usage, beams) in branches.into_iter().flatten() {
+            let merged = tally.entry(usage).or_default();
+            beams.into_iter().for_each(|(beams, count)| *merged.entry(beams).or_default() += count);
+        }
+        Ok(tally)
+    }
+
+    /// Counts the partial worlds of the other half that each partial world of this half completes into.
+    fn weigh(&self, tally: &Tally, other: &Half, theirs: &Tally) -> Result<Tally> {
+        let target = self.pack(self.ceremonies.iter().map(|&(_, beams)| beams));
+        tally
+            .iter()
+            .map(|(&usage, beams)| {
+                let partners = self.partners(usage, other.members.len());
+                let weights = beams
+                    .keys()
+                    .map(|&beams| {
+                        // Every partial world is feasible, so it never lights up more beams than a ceremony did, and the
+                        // beams that remain can be unpacked one ceremony at a time.
+                        let rest = target - beams;
+                        let weight = partners
+                            .iter()
+                            .filter_map(|partner| theirs.get(partner).and_then(|beams| beams.get(&rest)))
+                            .try_fold(0u64, |weight, &count| weight.checked_add(count))
+                            .context("too many worlds to count")?;
+                        Ok((beams, weight))
+                    })
+                    .collect::<Result<_>>()?;
+                Ok((usage, weights))
+            })
+            .collect()
+    }
+
+    /// Lists every usage of the other half that completes a usage of this half.
+    ///
+    /// In a bipartite season, every column must end up matched at least once and at most as often as its capacity allows,
+    /// by exactly as many rows as the other half places. In a single pool, the lower half takes exactly the contestants
+    /// that the upper half leaves open.
+    fn partners(&self, usage: u64, rows: usize) -> Vec<u64> {
+        if self.kind == SeasonKind::NonBipartite {
+            return vec![usage];
+        }
+
+        let (all, cols) = self.allowed.dim();
+        let capacity = 1 + (all > cols) as u8;
+        let usage: Vec<u8> = self.places.iter().map(|&place| (usage / place % (capacity as u64 + 1)) as u8).collect();
+        let mut partners = vec![];
+        let mut partner = Vec::with_capacity(cols);
+        complete(&usage, capacity, rows, &mut partner, &mut partners);
+        partners
+            .into_iter()
+            .map(|partner| partner.iter().zip(&self.places).map(|(&uses, &place)| uses as u64 * place).sum())
+            .collect()
+    }
+
+    /// Walks through every feasible partial world of this half, calling `visit` with each one.
+    ///
+    /// The walk is split across threads at its first choice, and each branch visits its own state, which starts out as
+    /// `init()`. Returns the state of every branch.
+    fn walk<T: Send>(
+        &self,
+        init: impl Fn() -> T + Sync,
+        visit: impl Fn(&mut T, &Partial) + Sync,
+    ) -> Result<Vec<T>> {
+        let (rows, cols) = self.allowed.dim();
+        let usage = match self.kind {
+            SeasonKind::Bipartite => cols,
+            SeasonKind::NonBipartite => rows - self.middle,
+        };
+        let root = Partial {
+            couples: vec![],
+            placed: vec![false; rows],
+            usage: vec![0; usage],
+            beams: vec![0; self.ceremonies.len()],
+            open: self
+                .ceremonies
+                .iter()
+                .map(|(strip, _)| self.members.clone().filter(|&a| self.seat(strip, a)).count())
+                .collect(),
+            usage_key: 0,
+            beams_key: 0,
+            leaves: 0,
+        };
+
+        let Some(a) = self.members.clone().next() else {
+            let mut state = init();
+            visit(&mut state, &root);
+            return Ok(vec![state]);
+        };
+
+        let (states, leaves): (Vec<T>, Vec<usize>) = self
+            .options(a)
+            .filter(|&b| self.available(&root, a, b))
+            .collect_vec()
+            .into_par_iter()
+            .map(|b| {
+                let (mut partial, mut state) = (root.clone(), init());
+                self.branch(&mut partial, a, b, &mut state, &visit);
+                (state, partial.leaves)
+            })
+            .unzip();
+
+        match leaves.into_iter().sum::<usize>() > MAX_HALF_WORLDS {
+            true => Err(anyhow!("too many partial worlds to meet in the middle")),
+            _ => Ok(states),
+        }
+    }
+
+    /// Places the next contestant of this half in every possible way, and walks on from each one.
+    fn step<T>(&self, partial: &mut Partial, state: &mut T, visit: &impl Fn(&mut T, &Partial)) {
+        if partial.leaves > MAX_HALF_WORLDS {
+            return;
+        }
+
+        let Some(a) = self.members.clone().find(|&a| !partial.placed[a]) else {
+            partial.leaves += 1;
+            visit(state, partial);
+            return;
+        };
+
+        for b in self.options(a) {
+            if self.available(partial, a, b) {
+                self.branch(partial, a, b, state, visit);
+            }
+        }
+    }
+
+    /// Places a contestant with one of their options, walks on if that is still feasible, and then takes them back out.
+    fn branch<T>(&self, partial: &mut Partial, a: usize, b: usize, state: &mut T, visit: &impl Fn(&mut T, &Partial)) {
+        self.place(partial, a, b);
+        if self.feasible(partial) {
+            self.step(partial, state, visit);
+        }
+        self.unplace(partial, a, b);
+    }
+
+    /// Lists every option for a contestant, before checking them against the partial world.
+    ///
+    /// In a single pool, `NO_MATCH` leaves a contestant of the upper half open for the lower half to take.
+    fn options(&self, a: usize) -> impl Iterator<Item = usize> {
+        let (rows, cols) = self.allowed.dim();
+        let (open, matches) = match self.kind {
+            SeasonKind::Bipartite => (None, 0..cols),
+            SeasonKind::NonBipartite => (Some(NO_MATCH), (a + 1)..rows),
+        };
+        open.into_iter().chain(matches)
+    }
+
+    /// Determines whether a contestant can be placed with one of their options in a partial world.
+    fn available(&self, partial: &Partial, a: usize, b: usize) -> bool {
+        let (rows, cols) = self.allowed.dim();
+        match (self.kind, b) {
+            (SeasonKind::Bipartite, _) => self.allowed[(a, b)] && partial.usage[b] < 1 + (rows > cols) as u8,
+            (SeasonKind::NonBipartite, NO_MATCH) => a >= self.middle,
+            (SeasonKind::NonBipartite, _) => !partial.placed[b] && self.allowed[(a, b)],
+        }
+    }
+
+    /// Places a couple into a partial world, or leaves a contestant open if their match is `NO_MATCH`.
+    fn place(&self, partial: &mut Partial, a: usize, b: usize) {
+        self.mark(partial, a, true);
+        let used = match (self.kind, b) {
+            (SeasonKind::Bipartite, _) => Some(b),
+            (SeasonKind::NonBipartite, NO_MATCH) => Some(a - self.middle),
+            (SeasonKind::NonBipartite, _) => {
+                self.mark(partial, b, true);
+                (a < self.middle && b >= self.middle).then(|| b - self.middle)
+            }
+        };
+        if let Some(i) = used {
+            partial.usage[i] += 1;
+            partial.usage_key += self.places[i];
+        }
+
+        if b != NO_MATCH {
+            partial.couples.push((a, b));
+            for (c, ((strip, _), beams)) in self.ceremonies.iter().zip(partial.beams.iter_mut()).enumerate() {
+                if strip[a] == b {
+                    *beams += 1;
+                    partial.beams_key += 1 << (c as u32 * self.width);
+                }
+            }
+        }
+    }
+
+    /// Takes a couple back out of a partial world, or takes back a contestant that was left open.
+    fn unplace(&self, partial: &mut Partial, a: usize, b: usize) {
+        if b != NO_MATCH {
+            partial.couples.pop();
+            for (c, ((strip, _), beams)) in self.ceremonies.iter().zip(partial.beams.iter_mut()).enumerate() {
+                if strip[a] == b {
+                    *beams -= 1;
+                    partial.beams_key -= 1 << (c as u32 * self.width);
+                }
+            }
+        }
+
+        self.mark(partial, a, false);
+        let used = match (self.kind, b) {
+            (SeasonKind::Bipartite, _) => Some(b),
+            (SeasonKind::NonBipartite, NO_MATCH) => Some(a - self.middle),
+            (SeasonKind::NonBipartite, _) => {
+                self.mark(partial, b, false);
+                (a < self.middle && b >= self.middle).then(|| b - self.middle)
+            }
+        };
+        if let Some(i) = used {
+            partial.usage[i] -= 1;
+            partial.usage_key -= self.places[i];
+        }
+    }
+
+    /// Marks a contestant as placed or not, keeping track of the seats that this half could still light up.
+    fn mark(&self, partial: &mut Partial, a: usize, placed: bool) {
+        partial.placed[a] = placed;
+        if !self.members.contains(&a) {
+            return;
+        }
+
+        self.ceremonies
+            .iter()
+            .zip(partial.open.iter_mut())
+            .filter(|((strip, _), _)| self.seat(strip, a))
+            .for_each(|(_, open)| match placed {
+                true => *open -= 1,
+                _ => *open += 1,
+            });
+    }
+
+    /// Determines whether a partial world could still complete into a consistent world.
+    ///
+    /// No ceremony can have passed its number of beams, and every ceremony must still be able to reach it with the seats
+    /// that this half has not placed yet and the seats of the other half.
+    fn feasible(&self, partial: &Partial) -> bool {
+        self.ceremonies
+            .iter()
+            .zip(&partial.beams)
+            .zip(&partial.open)
+            .zip(&self.elsewhere)
+            .all(|((((_, target), &beams), &open), &elsewhere)| {
+                let beams = beams as usize;
+                beams <= *target && beams + open + elsewhere >= *target
+            })
+    }
+}
+
+/// Lists every way to use each remaining column so that it is matched between once and `capacity` times in total, with
+/// exactly `rows` rows left to place.
+fn complete(usage: &[u8], capacity: u8, rows: usize, partner: &mut Vec<u8>, partners: &mut Vec<Vec<u8>>) {
+    let Some((&used, rest)) = usage.split_first() else {
+        if rows == 0 {
+            partners.push(partner.clone());
+        }
+        return;
+    };
+
+    for uses in (used == 0) as u8..=capacity.saturating_sub(used) {
+        if uses as usize > rows {
+            break;
+        }
+        partner.push(uses);
+        complete(rest, capacity, rows - uses as usize, partner, partners);
+        partner.pop();
+    }
+}
+
+impl Hasher for KeyHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|&byte| self.write_u64(byte as u64));
+    }
+
+    fn write_u64(&mut self, key: u64) {
+        self.0 = (self.0.rotate_left(5) ^ key).wrapping_mul(0x9e3779b97f4a7c15);
+    }
+}
@@ -3,47 +3,85 @@ use std::iter;
 use crate::prelude::*;
 use tabled::builder::Builder;
 
-impl<'a> Season<'a> {
+impl Season {
     /// Pretty-prints a season.
-    pub fn pretty_print(&self, as_counts: bool) -> () {
-        let (turn, worlds) = (self.turn, self.worlds.len());
-        let specifier = if worlds == 1 { "world" } else { "worlds" }; 
+    pub fn pretty_print(&self, as_counts: bool) {
+        if self.engine().is_none() {
+            let (turn, events) = (self.turn, self.evidence.len());
+            println!("Episode {turn} - worlds have not been materialised ({events} events recorded)");
+            return;
+        }
+
+        let (turn, worlds) = (self.turn, self.num_worlds());
+        let specifier = match (worlds, self.sampling) {
+            (1, None) => "world remains",
+            (_, None) => "worlds remain",
+            (1, Some(_)) => "sampled world",
+            (_, Some(_)) => "sampled worlds",
+        };
 
         println!(
-            "Episode {turn} - {worlds} {specifier} remain\n{}",
+            "Episode {turn} - {worlds} {specifier}\n{}",
             self.table(as_counts).with(Style::rounded()).with(Alignment::right())
         );
     }
 
-    /// Coerces a value into its string representation.
-    fn print_value(&self, e: f32, factor: f32, as_counts: bool) -> String {
-        if e == factor {
-            "♡".into()
-        } else if e == 0.0 {
+    /// Coerces a couple count into its string representation, as either a count or a probability.
+    ///
+    /// In approximate mode, counts are always shown as probabilities with their margin of error, and a couple is only
+    /// blank or certain if the truth booths alone make it so.
+    fn print_value(&self, count: u64, allowed: ArrayView1<bool>, f: usize, as_counts: bool) -> String {
+        let nw = self.num_worlds() as u64;
+        if self.sampling.is_some() {
+            return match (allowed[f], allowed.iter().filter(|&&b| b).count()) {
+                (false, _) => " ".into(),
+                (true, 1) => "♡".into(),
+                _ => self.print_estimate(count),
+            };
+        }
+
+        if count == 0 {
             " ".into()
+        } else if count == nw {
+            "♡".into()
         } else {
             match as_counts {
-                true => (e as usize).to_string(),
-                _ => format!("{:.1}%", e * 100.0),
+                true => count.to_string(),
+                _ => format!("{:.1}%", count as f64 / nw as f64 * 100.0),
             }
         }
     }
 
+    /// Coerces a sampled count into a probability with its margin of error, or a bound if no samples or every sample
+    /// are counted.
+    fn print_estimate(&self, count: u64) -> String {
+        let (p, margin) = self.estimate(count);
+        let bound = (margin * 100.0).max(0.1);
+        match count {
+            0 => format!("<{bound:.1}%"),
+            _ if count == self.num_worlds() as u64 => format!(">{:.1}%", 100.0 - bound),
+            _ => format!("{:.1}±{:.1}%", p * 100.0, margin * 100.0),
+        }
+    }
+
     /// Print a speculation table.
     pub fn speculate(&self, couples: Vec<CoupleInput>, prompt: &str) -> Result<()> {
-        let mut table = vec![vec![" ".to_owned(); self.n]; self.n];
-        for (m, f) in couples {
-            let (m, f) = (self.id(m, &self.ms)?, self.id(f, &self.fs)?);
+        let mut table = vec![vec![" ".to_owned(); self.f.len()]; self.m.len()];
+        for couple in couples {
+            let (m, f) = self.couple_id(couple)?;
             table[m][f] = "♡".into();
+            if self.kind == SeasonKind::NonBipartite {
+                table[f][m] = "♡".into();
+            }
         }
 
         let mut builder = Builder::new();
-        let header = iter::once("".into()).chain(self.f.clone().into_iter());
+        let header = iter::once("".into()).chain(self.f.clone());
         builder.push_record(header);
         self.m.iter().for_each(|m| {
             let index = self.id(m, &self.ms).unwrap();
             let data = &table[index];
-            let row = iter::once(m.into()).chain(data.clone().into_iter());
+            let row = iter::once(m.into()).chain(data.clone());
             builder.push_record(row);
         });
 
@@ -54,48 +92,55 @@ impl<'a> Season<'a> {
     }
 
     /// Print a speculation table that shows the probability that each number of beams is correct.
+    ///
+    /// In approximate mode, each probability comes with its margin of error, and the sample size is shown as well. While
+    /// only truth booths have been recorded, this is counted analytically, so the worlds need not be materialised.
     pub fn spread(&self, couples: Vec<CoupleInput>) -> Result<()> {
-        let nw = self.num_worlds() as f32;
+        let strip = self.strip(&couples)?;
+        let worlds: Vec<usize> = self.worlds.iter().collect();
+        let histogram = self.histogram_of(&strip, &worlds, false)?;
+
         let mut score = 0.0;
-        let p: Vec<String> = (0..=self.n).into_iter().map(|k| {
-            let mut s = self.clone();
-            s.apply_ceremony(couples.clone(), k, true)?;
-            let p = (s.num_worlds() as f32 / nw) * 100.0;
-            score += k as f32 * p;
-            Ok(format!("{p:.1}%"))
-        }).try_collect::<String, Vec<String>, Error>()?;
-        score /= self.n as f32;
+        let p: Vec<String> = histogram.sizes.iter().enumerate().map(|(k, &size)| {
+            let (p, _) = self.estimate(size as u64);
+            score += k as f64 * p * 100.0;
+            match self.sampling {
+                Some(_) => self.print_estimate(size as u64),
+                None => format!("{:.1}%", p * 100.0),
+            }
+        }).collect();
+        score /= self.n as f64;
 
         let mut builder = Builder::new();
         let header = iter::once("n".to_owned()).chain((0..=self.n).map(|k| k.to_string()));
         builder.push_record(header);
-        let data = iter::once("p".into()).chain(p.into_iter());
+        let data = iter::once("p".into()).chain(p);
         builder.push_record(data);
         let mut table = builder.build();
 
-        println!("Episode {} - ceremony distribution ({score:.1})%\n{}", self.turn, table.with(Style::rounded()).with(Alignment::right()));
+        let samples = match self.sampling {
+            Some(_) => format!(" from {} samples", self.num_worlds()),
+            None => "".into(),
+        };
+
+        println!("Episode {} - ceremony distribution ({score:.1})%{samples}\n{}", self.turn, table.with(Style::rounded()).with(Alignment::right()));
         Ok(())
     }
 
     /// Returns a table representing the current state of the season,
     /// in terms of how many worlds remain for each couple.
     pub fn table(&self, as_counts: bool) -> Table {
-        let factor = if as_counts {
-            self.worlds.len() as f32
-        } else {
-            1.0
-        };
-        let table = self.distribution.clone() * factor;
-
+        let allowed = self.enumerator().allowed_couples();
         let mut builder = Builder::new();
-        let header = iter::once("".into()).chain(self.f.clone().into_iter());
+        let header = iter::once("".into()).chain(self.f.clone());
         builder.push_record(header);
         self.m.iter().for_each(|m| {
             let index = self.id(m, &self.ms).unwrap();
-            let data = table.slice(s![index, ..]).to_vec();
+            let data = self.counts.slice(s![index, ..]).to_vec();
             let row = iter::once(m.into()).chain(
                 data.into_iter()
-                    .map(|e| self.print_value(e, factor, as_counts)),
+                    .enumerate()
+                    .map(|(f, count)| self.print_value(count, allowed.row(index), f, as_counts)),
             );
             builder.push_record(row);
         });
@@ -0,0 +1,156 @@
+use super::symmetry;
+use crate::prelude::*;
+
+/// The worlds that remain after a sequence of moves, over every outcome.
+#[derive(Clone, Copy, Debug)]
+struct Remaining {
+    /// The expected number of worlds that remain, weighted by how many worlds lead to each outcome.
+    expected: f64,
+    /// The most worlds that could remain, whatever the outcomes.
+    worst: usize,
+}
+
+impl Season {
+    /// Looks ahead over a sequence of moves, to see how well each first move sets up the moves after it.
+    ///
+    /// Every first move of the given kind is tried: every couple for a truth booth, and the best ceremony under every
+    /// objective for a ceremony, since there are far too many line-ups to try them all. Every later move is the greedy
+    /// pick under the given objective, in every outcome of the moves before it, and each outcome is played out on a copy
+    /// of the season. Truth booths light up no beams, so they are picked by minimax unless the objective is information.
+    ///
+    /// Plans are ranked by the expected number of worlds that remain after the last move, and then by the most that
+    /// could; couples that the evidence cannot tell apart are only played out once.
+    pub fn lookahead(&self, moves: &[Move], objective: Objective) -> Result<Vec<Plan>> {
+        self.view()?;
+        if self.sampling.is_some() {
+            return Err(anyhow!("cannot look ahead over sampled worlds; use `materialise` first"));
+        }
+
+        let Some((&first, rest)) = moves.split_first() else {
+            return Ok(vec![]);
+        };
+        if self.num_worlds() == 0 {
+            return Ok(vec![]);
+        }
+
+        let candidates: Vec<Vec<Couple>> = match first {
+            Move::Truth => self.couples().into_iter().map(|couple| vec![couple]).collect(),
+            Move::Ceremony => [
+                Objective::Minimax,
+                Objective::FollowUp,
+                Objective::Information,
+                Objective::Blackout,
+                Objective::Beams,
+            ]
+            .into_iter()
+            .filter_map(|objective| self.greedy(Move::Ceremony, objective))
+            .unique()
+            .collect(),
+        };
+
+        let orbit = match first {
+            Move::Truth => symmetry::orbits(&candidates, &self.automorphisms(), |couples, relabeling| {
+                couples.iter().map(|&couple| self.relabel_couple(couple, relabeling)).collect()
+            }),
+            Move::Ceremony => (0..candidates.len()).collect(),
+        };
+
+        let mut played: Vec<Option<Remaining>> = vec![None; candidates.len()];
+        for (i, couples) in candidates.iter().enumerate() {
+            played[i] = match played[orbit[i]] {
+                Some(remaining) => Some(remaining),
+                None => Some(self.play(first, couples, rest, objective)?),
+            };
+        }
+
+        let greedy = self.greedy(first, objective);
+        let plans = candidates
+            .into_iter()
+            .zip(played)
+            .filter_map(|(couples, remaining)| Some((couples, remaining?)))
+            .map(|(couples, Remaining { expected, worst })| Plan {
+                greedy: greedy.as_ref() == Some(&couples),
+                couples: couples
+                    .into_iter()
+                    .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
+                    .collect(),
+                expected,
+                worst,
+            })
+            .sorted_by(|a, b| a.expected.total_cmp(&b.expected).then(a.worst.cmp(&b.worst)))
+            .collect();
+
+        Ok(plans)
+    }
+
+    /// Picks the move of the given kind that the greedy solvers would, if there is one.
+    pub(super) fn greedy(&self, kind: Move, objective: Objective) -> Option<Vec<Couple>> {
+        match kind {
+            Move::Truth => {
+                let objective = match objective {
+                    Objective::Information => Objective::Information,
+                    _ => Objective::Minimax,
+                };
+                let (couple, _, _) = self.find_best_truth_impl(None, objective).into_iter().next()?;
+                Some(vec![couple])
+            }
+            Move::Ceremony => {
+                let (couples, _) = self.find_best_ceremony_impl(objective, Candidates::Worlds).into_iter().next()?;
+                Some(couples)
+            }
+        }
+    }
+
+    /// Plays a move out over every one of its outcomes, and then the greedy picks for the rest of the moves after each.
+    fn play(&self, kind: Move, couples: &[Couple], rest: &[Move], objective: Objective) -> Result<Remaining> {
+        let total = self.num_worlds() as f64;
+        let mut remaining = Remaining { expected: 0.0, worst: 0 };
+        for (_, branch) in self.outcomes(kind, couples)? {
+            let after = branch.remaining(rest, objective)?;
+            remaining.expected += branch.num_worlds() as f64 / total * after.expected;
+            remaining.worst = remaining.worst.max(after.worst);
+        }
+
+        Ok(remaining)
+    }
+
+    /// Plays out the greedy picks for a sequence of moves, over every outcome.
+    fn remaining(&self, moves: &[Move], objective: Objective) -> Result<Remaining> {
+        let worlds = self.num_worlds();
+        let leaf = Remaining { expected: worlds as f64, worst: worlds };
+        let Some((&next, rest)) = moves.split_first() else {
+            return Ok(leaf);
+        };
+
+        match self.greedy(next, objective) {
+            Some(couples) => self.play(next, &couples, rest, objective),
+            None => Ok(leaf),
+        }
+    }
+
+    /// Applies every outcome of a move to its own copy of the season, keeping only the outcomes that could happen.
+    pub(super) fn outcomes(&self, kind: Move, couples: &[Couple]) -> Result<Vec<(Outcome, Season)>> {
+        let mut branches = vec![];
+        match kind {
+            Move::Truth => {
+                for correct in [true, false] {
+                    let mut branch = self.clone();
+                    branch.apply_truth_impl(couples[0], correct)?;
+                    branches.push((Outcome::Match(correct), branch));
+                }
+            }
+            Move::Ceremony => {
+                let mut strip = vec![NO_MATCH; self.m.len()];
+                couples.iter().for_each(|&(m, f)| strip[m] = f);
+                for beams in 0..=self.n {
+                    let mut branch = self.clone();
+                    branch.apply_ceremony_impl(&strip, beams)?;
+                    branches.push((Outcome::Beams(beams), branch));
+                }
+            }
+        }
+
+        branches.retain(|(_, branch)| branch.num_worlds() != 0);
+        Ok(branches)
+    }
+}
@@ -1,15 +1,28 @@
 use crate::prelude::*;
 
 mod accessors;
+pub mod bitset;
+pub mod components;
 mod constructor;
+mod cover;
+mod decisions;
+pub mod enumerator;
+mod game;
+pub mod halves;
 mod io;
+mod lookahead;
 mod mutators;
+pub mod permanent;
+pub mod sampler;
+mod search;
 mod solvers;
+mod symmetry;
 pub mod types;
 pub mod worldview;
 
 #[derive(Clone, Debug)]
-pub struct Season<'a> {
+pub struct Season {
+    kind: SeasonKind,
     m: Vec<String>,
     f: Vec<String>,
     ms: HashMap<String, usize>,
@@ -19,7 +32,11 @@ pub struct Season<'a> {
     turn: usize,
     found: usize,
 
-    distribution: Array2<f32>,
-    worlds: Vec<usize>,
-    worldview: &'a Vec<Vec<usize>>,
+    engine: Option<Engine>,
+    total: u64,
+    counts: Array2<u64>,
+    evidence: Vec<Evidence>,
+    sampling: Option<Sampling>,
+    worlds: Bitset,
+    worldview: Option<Arc<Worldview>>,
 }
@@ -1,58 +1,60 @@
-use std::usize;
+use std::path::Path;
 
 use crate::prelude::*;
 
-impl<'a> Season<'a> {
+impl Season {
     /// Applies a given list of couples and a number of correct couples as a matching ceremony.
     pub fn apply_ceremony(
         &mut self,
         couples: Vec<CoupleInput>,
         beams: usize,
-        recompute: bool,
     ) -> Result<&mut Self> {
-        let mapped: Vec<usize> = couples
-            .into_iter()
-            // Validate the input to ensure we can convert from names to ids.
-            .map(|(m, f)| {
-                Ok::<(usize, usize), Error>((self.id(m, &self.ms)?, self.id(f, &self.fs)?))
-            })
-            .try_collect::<(usize, usize), Vec<(usize, usize)>, Error>()?
-            // Process the couples, and convert the list into a single world representation.
-            .into_iter()
-            .sorted_by_key(|(m, _)| *m)
-            .map(|(_, f)| f)
-            .collect();
+        let strip = self.strip(&couples)?;
+        self.apply_ceremony_impl(&strip, beams)
+    }
+
+    /// Validates a list of couples that sit at a ceremony, and converts it into a match strip.
+    pub(super) fn strip(&self, couples: &[CoupleInput]) -> Result<Vec<usize>> {
+        if couples.len() != self.n {
+            return Err(anyhow!("expected {} couples; received {} instead", self.n, couples.len()));
+        }
 
-        self.apply_ceremony_impl(&mapped, beams)?;
+        // Columns are seated after the rows, unless both come from the same pool.
+        let offset = match self.kind {
+            SeasonKind::Bipartite => self.m.len(),
+            SeasonKind::NonBipartite => 0,
+        };
 
-        match recompute {
-            true => self.recalculate(),
-            _ => Ok(self),
+        let mut mapped = vec![NO_MATCH; self.m.len()];
+        let mut seated = vec![false; self.m.len() + self.f.len()];
+        for couple in couples.iter() {
+            // Validate the input to ensure we can convert from names to ids, that nobody sits with themselves in a single
+            // pool, and that nobody sits twice.
+            let (m, f) = self.canonical(self.couple_id(*couple)?);
+            if !self.is_couple((m, f)) {
+                return Err(anyhow!("{} cannot be their own match", couple.0));
+            }
+            if seated[m] || seated[f + offset] {
+                return Err(anyhow!("{} or {} is seated more than once", couple.0, couple.1));
+            }
+            mapped[m] = f;
+            (seated[m], seated[f + offset]) = (true, true);
         }
+
+        Ok(mapped)
     }
 
     /// Applies a given list of couples and a number of correct couples as a matching ceremony.
     ///
     /// Here, `couples` is a match strip of the form `m = [f_1, f_2, ..., f_n]` where `match_of(m_i) = m[m_i]`.
-    /// However, couples that are already found are set to n + 1 instead, to simulate a non-match. This is necessary
-    /// for blackout detection.
+    /// However, rows that do not sit with anybody are set to `NO_MATCH` instead, to simulate a non-match. This is
+    /// necessary for seasons with unequal groups, as well as for blackout detection.
     pub(super) fn apply_ceremony_impl(
         &mut self,
-        couples: &Vec<usize>,
+        couples: &[usize],
         beams: usize,
     ) -> Result<&mut Self> {
-        self.worlds
-            // Keep only those indices for which the corresponding world matches the ceremonial world in exactly `beams` places.
-            .retain(|&i| {
-                unsafe { self.worldview.get_unchecked(i) }
-                    .iter()
-                    .zip(couples)
-                    .filter(|(&lhs, &rhs)| lhs == rhs)
-                    .count()
-                    == beams
-            });
-
-        Ok(self)
+        self.apply_evidence(Evidence::Ceremony(couples.to_vec(), beams))
     }
 
     /// Sends a given couple to the truth booth and applies the given outcome.
@@ -60,31 +62,95 @@ impl<'a> Season<'a> {
         &mut self,
         couple: CoupleInput,
         correct: bool,
-        recompute: bool,
     ) -> Result<&mut Self> {
-        let (m, f) = couple;
+        let (name, _) = couple;
+        let couple = self.couple_id(couple)?;
+        if !self.is_couple(self.canonical(couple)) {
+            return Err(anyhow!("{name} cannot be their own match"));
+        }
 
-        let couple = (self.id(m, &self.ms)?, self.id(f, &self.fs)?);
+        self.apply_truth_impl(couple, correct)
+    }
 
-        self.apply_truth_impl(couple, correct)?;
+    // Sends a given couple to the truth booth and applies the given outcome.
+    pub(super) fn apply_truth_impl(&mut self, couple: Couple, correct: bool) -> Result<&mut Self> {
+        self.apply_evidence(Evidence::Truth(couple, correct))
+    }
 
-        match recompute {
-            true => self.recalculate(),
-            _ => Ok(self),
+    /// Records a piece of evidence, and brings the counts up to date with it.
+    fn apply_evidence(&mut self, evidence: Evidence) -> Result<&mut Self> {
+        self.evidence.push(evidence.clone());
+        let (Some(view), None) = (self.worldview.clone(), self.sampling) else {
+            return self.refresh();
+        };
+
+        let removed = self.eliminate(&view, &evidence);
+        self.forget(&view, &removed)
+    }
+
+    /// Removes every world that breaks a piece of evidence from the remaining worlds, and returns the removed worlds.
+    fn eliminate(&mut self, view: &Worldview, evidence: &Evidence) -> Bitset {
+        let mut removed = self.worlds.clone();
+        if view.is_empty() {
+            return removed;
+        }
+
+        match evidence {
+            Evidence::Ceremony(couples, beams) => {
+                let couples = worldview::pack(couples);
+                self.worlds
+                    // Keep only those indices for which the corresponding world matches the ceremonial world in exactly `beams` places.
+                    .par_retain(|i| worldview::beams(unsafe { view.get_unchecked(i) }, &couples) == *beams);
+                removed.and_not_assign(&self.worlds);
+            }
+            // Mapped worldviews are too large to index, so they are scanned instead.
+            &Evidence::Truth((m, f), correct) if view.is_mapped() => {
+                self.worlds
+                    .par_retain(|i| (unsafe { view.get_unchecked(i) }[m] as usize == f) == correct);
+                removed.and_not_assign(&self.worlds);
+            }
+            &Evidence::Truth(couple, correct) => {
+                let worlds = view.couple_index(self.f.len()).couple(couple);
+                match correct {
+                    true => {
+                        self.worlds.and_assign(worlds);
+                        removed.and_not_assign(worlds);
+                    }
+                    _ => {
+                        self.worlds.and_not_assign(worlds);
+                        removed.and_assign(worlds);
+                    }
+                };
+            }
         }
+
+        removed
     }
 
-    // Sends a given couple to the truth booth and applies the given outcome.
-    pub(super) fn apply_truth_impl(&mut self, couple: Couple, correct: bool) -> Result<&mut Self> {
-        let (m, f) = couple;
+    /// Takes a set of eliminated worlds out of the couple counts.
+    ///
+    /// When only a few worlds were eliminated, it is cheaper to decrement the counts for each of them; otherwise, the
+    /// counts are rebuilt from the index, or from a scan of the remaining worlds if the worldview is mapped.
+    fn forget(&mut self, view: &Worldview, removed: &Bitset) -> Result<&mut Self> {
+        let decrements = removed.count() * self.m.len();
+        let rebuild = match view.is_mapped() {
+            true => self.worlds.count() * self.m.len(),
+            _ => self.counts.len() * self.worlds.len().div_ceil(64),
+        } / rayon::current_num_threads();
+
+        if decrements > rebuild {
+            return self.recalculate();
+        }
 
-        self.worlds
-            .retain(|&i| { 
-                let check = unsafe { *self.worldview.get_unchecked(i).get_unchecked(m) };
-                (check == f) == correct
+        self.total -= removed.count() as u64;
+        removed.iter().for_each(|k| {
+            let world = unsafe { view.get_unchecked(k) };
+            world.iter().enumerate().for_each(|(m, &f)| {
+                self.counts[(m, f as usize)] -= 1;
             });
+        });
 
-        Ok(self)
+        Ok(self.update_found())
     }
 
     /// Ticks over to the next turn, and errors to signal that you have lost.
@@ -96,22 +162,127 @@ impl<'a> Season<'a> {
         }
     }
 
-    /// Recomputes the bipartite probability table for this season.
+    /// Enumerates the worlds that are consistent with the evidence recorded so far, and tracks them from now on.
+    ///
+    /// This never generates a world that breaks a truth booth or a ceremony, so it is the only way to materialise the
+    /// worlds of a large season. On a season that is already materialised, this compacts its worldview.
+    pub fn materialise(&mut self) -> Result<&mut Self> {
+        self.sampling = None;
+        let view = self.enumerator().enumerate();
+        self.worlds = Bitset::full(view.len());
+        self.worldview = Some(Arc::new(view));
+        self.recalculate()
+    }
+
+    /// Materialises this season from a cache of every world on disk, and replays the evidence recorded so far over it.
+    ///
+    /// The cache for this shape of season is generated in `dir` the first time, and mapped rather than read from then on,
+    /// so seasons too large for memory only keep the set of remaining worlds resident, at one bit per world.
+    pub fn materialise_cached(&mut self, dir: &Path) -> Result<&mut Self> {
+        self.sampling = None;
+        let view = Arc::new(worldview::cached(self.kind, self.m.len(), self.f.len(), dir)?);
+        self.worlds = Bitset::full(view.len());
+        self.worldview = Some(view.clone());
+
+        for evidence in self.evidence.clone() {
+            self.eliminate(&view, &evidence);
+        }
+        self.recalculate()
+    }
+
+    /// Switches this season to approximate mode, where it tracks a sample of its consistent worlds instead of all of them.
+    ///
+    /// Every piece of evidence from now on draws a fresh sample, so that the sample never shrinks as worlds are ruled out.
+    /// Use `materialise` to go back to exact mode.
+    pub fn sample(&mut self, samples: usize, seed: u64) -> Result<&mut Self> {
+        if samples == 0 {
+            return Err(anyhow!("expected at least one sample"));
+        }
+
+        self.sampling = Some(Sampling { samples, seed });
+        self.recalculate()
+    }
+
+    /// Recounts this season if it can be counted, or leaves it uncounted until it is materialised.
+    pub(super) fn refresh(&mut self) -> Result<&mut Self> {
+        if let Err(e) = self.recalculate() {
+            debug!("leaving season uncounted: {e}");
+        }
+        Ok(self)
+    }
+
+    /// Rebuilds the couple counts for this season from scratch, using the best engine for its current state.
     pub fn recalculate(&mut self) -> Result<&mut Self> {
-        let nw = self.worlds.len() as f32;
-        
-        let mut view = self.distribution.view_mut();
-        (0..self.n).for_each(|m| {
-            view.slice_mut(s![m, ..]).fill(0.0);
-            self.worlds.iter().for_each(|&k| {
-                let f = unsafe { *self.worldview.get_unchecked(k).get_unchecked(m) };
-                view[(m, f)] += 1.0;
-            });
-        });
-        view /= nw;
+        self.engine = None;
+        match (self.sampling, self.is_materialised()) {
+            (Some(_), _) => self.recalculate_sampling(),
+            (_, true) => self.recalculate_enumeration(),
+            _ => self.recalculate_components(),
+        }
+    }
+
+    /// Counts the worlds of an unmaterialised season one independent component at a time, without enumerating them all.
+    fn recalculate_components(&mut self) -> Result<&mut Self> {
+        let enumerator = self.enumerator();
+        let (total, counts) = components::count(self.kind, &enumerator.allowed_couples(), enumerator.ceremonies())
+            .context("cannot count these worlds without materialising them; use `materialise` first")?;
 
-        self.found = self.distribution.iter().filter(|&e| *e == 1.0).count();
+        self.engine = Some(match enumerator.ceremonies().is_empty() {
+            true => Engine::Permanent,
+            _ => Engine::Components,
+        });
+        (self.total, self.counts) = (total, counts);
+        Ok(self.update_found())
+    }
 
+    /// Draws a fresh sample of the worlds that are consistent with the evidence, and counts it.
+    fn recalculate_sampling(&mut self) -> Result<&mut Self> {
+        let Sampling { samples, seed } = self.sampling.context("season is not in approximate mode")?;
+        let view = Sampler::new(self.kind, self.enumerator()).sample(samples, seed);
+        self.worlds = Bitset::full(view.len());
+        self.worldview = Some(Arc::new(view));
+        self.recalculate_enumeration()?;
+        self.engine = Some(Engine::Sampling);
         Ok(self)
     }
+
+    /// Rebuilds the couple counts for this season from its materialised worlds, in parallel.
+    fn recalculate_enumeration(&mut self) -> Result<&mut Self> {
+        let view = self.view()?.clone();
+        if view.is_empty() {
+            self.counts.fill(0);
+        } else if view.is_mapped() {
+            // Mapped worldviews are too large to index, so the remaining worlds are scanned instead.
+            self.counts = view.tally(&self.worlds, self.counts.dim());
+        } else {
+            // Every count is just the size of the intersection between the remaining worlds and the worlds that contain
+            // that couple, so each one is independent of the others.
+            let (index, worlds, cols) = (view.couple_index(self.f.len()), &self.worlds, self.f.len());
+            self.counts
+                .as_slice_mut()
+                .context("couple counts are not contiguous")?
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(i, count)| {
+                    *count = worlds.count_and(index.couple((i / cols, i % cols))) as u64;
+                });
+        }
+
+        self.total = self.worlds.count() as u64;
+        self.engine = Some(Engine::Enumeration);
+
+        Ok(self.update_found())
+    }
+
+    /// Recounts the couples that are perfect matches in every remaining world.
+    fn update_found(&mut self) -> &mut Self {
+        let nw = self.num_worlds() as u64;
+        self.found = self
+            .couples()
+            .into_iter()
+            .filter(|&couple| nw > 0 && self.counts[couple] == nw)
+            .count();
+
+        self
+    }
 }
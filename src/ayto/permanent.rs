@@ -0,0 +1,182 @@
+use crate::prelude::*;
+
+/// The largest number of states that a dynamic program is allowed to allocate.
+const MAX_STATES: usize = 1 << 24;
+
+/// The largest number of terms that a dynamic program over polynomials is allowed to allocate.
+const MAX_TERMS: usize = 1 << 25;
+
+/// Returns the number of states that counting a season of the given shape would take, if that is small enough to do.
+///
+/// Bipartite seasons are also limited to 20 rows, so that their counts cannot overflow.
+pub fn states(kind: SeasonKind, rows: usize, cols: usize) -> Option<usize> {
+    let states = match kind {
+        SeasonKind::Bipartite if rows > 20 => None,
+        SeasonKind::Bipartite => (2 + (rows > cols) as usize).checked_pow(cols as u32),
+        SeasonKind::NonBipartite => 1usize.checked_shl(rows as u32),
+    };
+    states.filter(|&s| s <= MAX_STATES)
+}
+
+/// Counts the worlds of a bipartite season, in total and per couple, without enumerating them.
+///
+/// Every row must be matched to an allowed column, and every column must be matched at least once, and at most twice if
+/// there are more rows than columns.
+///
+/// This is a forward-backward dynamic program over the number of times each column has been matched so far, with rows
+/// matched in order, so it takes O((capacity + 1)^cols · rows · cols) time. Returns `None` if that is too large, or if
+/// the counts could overflow.
+pub fn count_bipartite(allowed: &Array2<bool>) -> Option<(u64, Array2<u64>)> {
+    let (rows, cols) = allowed.dim();
+    let states = states(SeasonKind::Bipartite, rows, cols)?;
+    let capacity = 1 + (rows > cols) as usize;
+    let base = capacity + 1;
+
+    // The place value of each column in a state, and the number of rows placed in each state.
+    let place: Vec<usize> = (0..cols).map(|f| base.pow(f as u32)).collect();
+    let digit = |s: usize, f: usize| (s / place[f]) % base;
+    let level: Vec<usize> = (0..states).map(|s| (0..cols).map(|f| digit(s, f)).sum()).collect();
+    let open = |s: usize, m: usize, f: usize| allowed[(m, f)] && digit(s, f) < capacity;
+
+    // The number of ways to reach each state from nobody being placed.
+    let mut forward = vec![0u64; states];
+    forward[0] = 1;
+    for s in 0..states {
+        let m = level[s];
+        if forward[s] == 0 || m >= rows {
+            continue;
+        }
+        for f in (0..cols).filter(|&f| open(s, m, f)) {
+            forward[s + place[f]] += forward[s];
+        }
+    }
+
+    // The number of ways to complete each state into a world where every column has been matched.
+    let mut backward = vec![0u64; states];
+    for s in (0..states).rev() {
+        let m = level[s];
+        backward[s] = match m.cmp(&rows) {
+            std::cmp::Ordering::Less => (0..cols)
+                .filter(|&f| open(s, m, f))
+                .map(|f| backward[s + place[f]])
+                .sum(),
+            std::cmp::Ordering::Equal => (0..cols).all(|f| digit(s, f) > 0) as u64,
+            std::cmp::Ordering::Greater => 0,
+        };
+    }
+
+    // Every world passes through exactly one state at each level, so a couple's count is the number of ways to reach a
+    // state at that row's level, times the number of ways to finish after placing that couple.
+    let mut counts = Array2::zeros((rows, cols));
+    for s in (0..states).filter(|&s| level[s] < rows && forward[s] != 0) {
+        let m = level[s];
+        for f in (0..cols).filter(|&f| open(s, m, f)) {
+            counts[(m, f)] += forward[s] * backward[s + place[f]];
+        }
+    }
+
+    Some((backward[0], counts))
+}
+
+/// Counts the perfect matchings of a single pool, in total and per couple, without enumerating them.
+///
+/// This is a dynamic program over the subsets of the pool that remain to be matched, always matching the lowest
+/// remaining contestant first, so it takes O(2^n · n) time. Returns `None` if that is too large, or if the counts could
+/// overflow.
+pub fn count_pool(allowed: &Array2<bool>) -> Option<(u64, Array2<u64>)> {
+    let (n, _) = allowed.dim();
+    let states = states(SeasonKind::NonBipartite, n, n)?;
+
+    // The number of perfect matchings of each subset of the pool.
+    let mut matchings = vec![0u64; states];
+    matchings[0] = 1;
+    for mask in 1..states {
+        let i = mask.trailing_zeros() as usize;
+        let rest = mask & !(1 << i);
+        matchings[mask] = (0..n)
+            .filter(|&j| rest & (1 << j) != 0 && allowed[(i, j)])
+            .map(|j| matchings[rest & !(1 << j)])
+            .sum();
+    }
+
+    // A couple's count is the number of ways to match everyone else once they are paired off.
+    let everyone = states - 1;
+    let counts = Array2::from_shape_fn((n, n), |(i, j)| match i != j && allowed[(i, j)] {
+        true => matchings[everyone & !(1 << i) & !(1 << j)],
+        _ => 0,
+    });
+
+    Some((matchings[everyone], counts))
+}
+
+/// Counts the worlds of a bipartite season by the number of beams that they would light up at a ceremony, without
+/// enumerating them.
+///
+/// This is the forward half of `count_bipartite`, except that each state keeps the rook polynomial of the ceremony's
+/// seats over the couples placed so far, that is, the number of ways to reach it with each number of beams lit. Returns
+/// `None` if that is too large.
+pub fn spread_bipartite(allowed: &Array2<bool>, strip: &[usize]) -> Option<Vec<u64>> {
+    let (rows, cols) = allowed.dim();
+    let states = states(SeasonKind::Bipartite, rows, cols)?;
+    let width = strip.iter().filter(|&&f| f != NO_MATCH).count() + 1;
+    states.checked_mul(width).filter(|&terms| terms <= MAX_TERMS)?;
+    let capacity = 1 + (rows > cols) as usize;
+    let base = capacity + 1;
+
+    let place: Vec<usize> = (0..cols).map(|f| base.pow(f as u32)).collect();
+    let digit = |s: usize, f: usize| (s / place[f]) % base;
+    let level: Vec<usize> = (0..states).map(|s| (0..cols).map(|f| digit(s, f)).sum()).collect();
+    let open = |s: usize, m: usize, f: usize| allowed[(m, f)] && digit(s, f) < capacity;
+
+    // A row only has one seat, so a state can never have lit up more beams than there are seats.
+    let mut ways = vec![0u64; states * width];
+    ways[0] = 1;
+    for s in 0..states {
+        let m = level[s];
+        if m >= rows || ways[s * width..(s + 1) * width].iter().all(|&w| w == 0) {
+            continue;
+        }
+        for f in (0..cols).filter(|&f| open(s, m, f)) {
+            let lit = (strip[m] == f) as usize;
+            for k in 0..width - lit {
+                ways[(s + place[f]) * width + k + lit] += ways[s * width + k];
+            }
+        }
+    }
+
+    let mut spread = vec![0u64; width];
+    for s in (0..states).filter(|&s| level[s] == rows && (0..cols).all(|f| digit(s, f) > 0)) {
+        spread.iter_mut().zip(&ways[s * width..]).for_each(|(total, &w)| *total += w);
+    }
+
+    Some(spread)
+}
+
+/// Counts the perfect matchings of a single pool by the number of beams that they would light up at a ceremony, without
+/// enumerating them.
+///
+/// This is `count_pool` with a rook polynomial in each state instead of a single count, where the ceremony records each
+/// couple against its lower id. Returns `None` if that is too large.
+pub fn spread_pool(allowed: &Array2<bool>, strip: &[usize]) -> Option<Vec<u64>> {
+    let (n, _) = allowed.dim();
+    let states = states(SeasonKind::NonBipartite, n, n)?;
+    let width = n / 2 + 1;
+    states.checked_mul(width).filter(|&terms| terms <= MAX_TERMS)?;
+
+    let mut matchings = vec![0u64; states * width];
+    matchings[0] = 1;
+    for mask in 1..states {
+        let i = mask.trailing_zeros() as usize;
+        let rest = mask & !(1 << i);
+        for j in (0..n).filter(|&j| rest & (1 << j) != 0 && allowed[(i, j)]) {
+            // The lowest remaining contestant is always the lower id of the couple.
+            let lit = (strip[i] == j) as usize;
+            let from = (rest & !(1 << j)) * width;
+            for k in 0..width - lit {
+                matchings[mask * width + k + lit] += matchings[from + k];
+            }
+        }
+    }
+
+    Some(matchings[(states - 1) * width..].to_vec())
+}
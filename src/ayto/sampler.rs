@@ -0,0 +1,177 @@
+use rand::{
+    rngs::StdRng,
+    seq::{index, SliceRandom},
+    Rng, SeedableRng,
+};
+
+use crate::prelude::*;
+
+/// The number of proposals per contestant that each chain makes before it draws its first sample.
+const BURN_IN: usize = 1000;
+
+/// The number of proposals per contestant that each chain makes between consecutive samples.
+const THINNING: usize = 20;
+
+/// The largest number of rows, or couples in a single pool, that a single proposal shuffles.
+const SHUFFLE: usize = 4;
+
+/// Samples worlds that are consistent with a season's evidence with a Markov chain, for seasons too large to enumerate.
+///
+/// Each step proposes a small change to the current world, like shuffling the matches of a few rows, and rejects it if it
+/// breaks a truth booth or changes the beams of a ceremony. Every proposal is exactly as likely as the one that undoes it,
+/// so each chain is uniform over the consistent worlds that it can reach from the first consistent world. Chains run in
+/// parallel, and each is seeded from a shared seed, so sampling is reproducible.
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    enumerator: Enumerator,
+    kind: SeasonKind,
+    allowed: Array2<bool>,
+}
+
+/// The state of a single chain.
+struct Chain {
+    /// The current world.
+    world: Vec<usize>,
+    /// The number of rows matched to each column in the current world.
+    uses: Vec<usize>,
+    /// The number of proposals that have been accepted so far.
+    accepted: usize,
+}
+
+impl Sampler {
+    /// Creates a sampler over the worlds that an enumerator would enumerate.
+    pub fn new(kind: SeasonKind, enumerator: Enumerator) -> Sampler {
+        let allowed = enumerator.allowed_couples();
+        Sampler {
+            enumerator,
+            kind,
+            allowed,
+        }
+    }
+
+    /// Draws the given number of worlds into a new worldview, which is empty if there are no consistent worlds.
+    ///
+    /// Worlds may repeat, since each sample is drawn independently of whether it has been drawn before.
+    pub fn sample(&self, samples: usize, seed: u64) -> Worldview {
+        let (rows, cols) = self.allowed.dim();
+        let Some(start) = self.enumerator.first() else {
+            return Worldview::new(rows);
+        };
+
+        let chains = rayon::current_num_threads().clamp(1, samples.max(1));
+        let (views, accepted): (Vec<Worldview>, Vec<usize>) = (0..chains)
+            .into_par_iter()
+            .map(|c| {
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(c as u64));
+                let mut chain = Chain {
+                    world: start.clone(),
+                    uses: start.iter().fold(vec![0; cols], |mut uses, &b| {
+                        uses[b] += 1;
+                        uses
+                    }),
+                    accepted: 0,
+                };
+
+                (0..BURN_IN * rows).for_each(|_| self.step(&mut chain, &mut rng));
+
+                let mut view = Worldview::with_capacity(rows, samples / chains + 1);
+                for _ in 0..(samples / chains + (c < samples % chains) as usize) {
+                    (0..THINNING * rows).for_each(|_| self.step(&mut chain, &mut rng));
+                    view.push(&chain.world);
+                }
+
+                (view, chain.accepted)
+            })
+            .unzip();
+
+        let proposals = chains * rows * BURN_IN + samples * rows * THINNING;
+        debug!("accepted {} of {proposals} proposals", accepted.iter().sum::<usize>());
+
+        views.into_iter().fold(Worldview::new(rows), |mut view, chain| {
+            view.append(chain);
+            view
+        })
+    }
+
+    /// Proposes a change to the world of a chain, and makes it if the world remains consistent.
+    fn step(&self, chain: &mut Chain, rng: &mut StdRng) {
+        let Some(changes) = self.propose(chain, rng) else {
+            return;
+        };
+
+        let consistent = changes.iter().all(|&(a, b)| self.allowed[(a, b)])
+            && self.enumerator.ceremonies().iter().all(|(strip, _)| {
+                // The world was consistent before, so its beams only need to stay the same.
+                let lit = |a: usize, b: usize| self.lit(strip, a, b) as isize;
+                changes
+                    .iter()
+                    .map(|&(a, b)| lit(a, b) - lit(a, chain.world[a]))
+                    .sum::<isize>()
+                    == 0
+            });
+
+        if !consistent {
+            return;
+        }
+
+        changes.into_iter().for_each(|(a, b)| {
+            chain.uses[chain.world[a]] -= 1;
+            chain.uses[b] += 1;
+            chain.world[a] = b;
+        });
+        chain.accepted += 1;
+    }
+
+    /// Proposes a change to the world of a chain, as a list of rows and their new matches.
+    ///
+    /// In a bipartite season, this shuffles the matches of a few rows, or moves a row from a column that is matched twice
+    /// to a column that is matched once. In a single pool, this breaks up a few couples and pairs them off at random.
+    /// Shuffling up to `SHUFFLE` rows or couples at once lets the chain step between worlds that light up the same beams
+    /// at a ceremony in different places.
+    fn propose(&self, chain: &Chain, rng: &mut StdRng) -> Option<Vec<Couple>> {
+        let (rows, cols) = self.allowed.dim();
+        let world = &chain.world;
+
+        match self.kind {
+            SeasonKind::Bipartite if rows > cols && rng.gen_bool(0.5) => {
+                let (a, b) = (rng.gen_range(0..rows), rng.gen_range(0..cols));
+                match (chain.uses[world[a]], chain.uses[b]) {
+                    (2, 1) => Some(vec![(a, b)]),
+                    _ => None,
+                }
+            }
+            SeasonKind::Bipartite => {
+                let k = rng.gen_range(2..=SHUFFLE).min(rows);
+                let picked = index::sample(rng, rows, k).into_vec();
+                let mut matches: Vec<usize> = picked.iter().map(|&a| world[a]).collect();
+                matches.shuffle(rng);
+                Some(picked.into_iter().zip(matches).collect())
+            }
+            SeasonKind::NonBipartite => {
+                let couples: Vec<usize> = (0..rows).filter(|&a| a < world[a]).collect();
+                let k = rng.gen_range(2..=SHUFFLE).min(couples.len());
+                let mut people: Vec<usize> = couples
+                    .choose_multiple(rng, k)
+                    .flat_map(|&a| [a, world[a]])
+                    .collect();
+                people.shuffle(rng);
+                Some(
+                    people
+                        .chunks_exact(2)
+                        .flat_map(|pair| [(pair[0], pair[1]), (pair[1], pair[0])])
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    /// Determines whether a couple lights up a beam for a ceremony.
+    ///
+    /// In a single pool, ceremonies record each couple against its lower id.
+    fn lit(&self, strip: &[usize], a: usize, b: usize) -> bool {
+        match self.kind {
+            SeasonKind::Bipartite => strip[a] == b,
+            SeasonKind::NonBipartite => a < b && strip[a] == b,
+        }
+    }
+}
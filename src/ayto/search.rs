@@ -0,0 +1,211 @@
+use rand::{
+    rngs::StdRng,
+    seq::{index, SliceRandom},
+    Rng, SeedableRng,
+};
+
+use super::solvers::{better, repaired};
+use crate::prelude::*;
+
+/// The largest number of remaining worlds that each candidate is scored against during a local search.
+const MAX_SEARCH_WORLDS: usize = 100_000;
+
+/// The temperature that a local search starts at, as a fraction of the score of its first candidate.
+const TEMPERATURE: f64 = 0.05;
+
+/// The state of a single chain of a local search.
+struct Walker {
+    /// The current ceremony, as a match strip.
+    strip: Vec<usize>,
+    /// The energy of the current ceremony, which the search tries to minimize.
+    energy: f64,
+    /// The best ceremony found so far, and its score.
+    best: (Vec<usize>, Option<f64>),
+    /// The score of every ceremony that has been proposed so far, so that none is scored twice.
+    seen: HashMap<Vec<usize>, Option<f64>>,
+    /// The number of proposals that have been made so far.
+    proposed: usize,
+    /// The number of proposals that have been accepted so far.
+    accepted: usize,
+}
+
+impl Season {
+    /// Searches for a high-scoring ceremony with simulated annealing, rather than by scoring every remaining world.
+    ///
+    /// Each chain starts from the ceremony of a random remaining world, and keeps proposing to swap the partners of two of
+    /// its couples. It always moves to a better ceremony, and sometimes to a worse one, less and less often as its budget
+    /// runs out. Ceremonies are scored just like in `find_best_ceremony`, but against a random sample of at most
+    /// `MAX_SEARCH_WORLDS` of the remaining worlds, unless they can be scored analytically. Histograms of a sample are
+    /// scaled up to every remaining world, so the returned scores are estimates in the same units as exact scores, such
+    /// as the number of remaining worlds left by the largest bucket. Chains run in parallel, and each is seeded from a
+    /// shared seed, so the search is reproducible as long as it is not stopped by its time limit.
+    pub fn search_best_ceremony(&self, objective: Objective, annealing: Annealing) -> Result<Vec<CeremonyScore>> {
+        if self.engine().is_some() && self.num_worlds() == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut rng = StdRng::seed_from_u64(annealing.seed);
+        let remaining: Vec<usize> = self.worlds.iter().collect();
+        let sampled = (remaining.len() > MAX_SEARCH_WORLDS).then_some(remaining.len());
+        let worlds: Vec<usize> = match sampled.is_some() {
+            true => index::sample(&mut rng, remaining.len(), MAX_SEARCH_WORLDS)
+                .into_iter()
+                .map(|i| remaining[i])
+                .sorted()
+                .collect(),
+            _ => remaining,
+        };
+
+        let score = |strip: &[usize]| -> Result<Option<f64>> {
+            let histogram = self.histogram_of(strip, &worlds, objective == Objective::FollowUp)?;
+            let histogram = match sampled {
+                Some(total) => histogram.scaled(total),
+                None => histogram,
+            };
+            Ok(histogram.score(strip, objective))
+        };
+
+        let started = Instant::now();
+        let chains = rayon::current_num_threads().clamp(1, annealing.iterations.max(1));
+        let results: Vec<(Vec<usize>, Option<f64>, usize, usize)> = (0..chains)
+            .into_par_iter()
+            .map(|c| {
+                let mut rng = StdRng::seed_from_u64(annealing.seed.wrapping_add(c as u64));
+                let strip = self.random_ceremony(&worlds, &mut rng);
+                let first = score(&strip)?;
+                let mut walker = Walker {
+                    strip: strip.clone(),
+                    energy: energy(first, objective),
+                    best: (strip.clone(), first),
+                    seen: HashMap::from([(strip, first)]),
+                    proposed: 0,
+                    accepted: 0,
+                };
+
+                // Scores come in the units of the objective, and some of them are small or even zero.
+                let temperature = match walker.energy.abs() * TEMPERATURE {
+                    t if t.is_finite() && t > 0.0 => t,
+                    _ => TEMPERATURE,
+                };
+
+                let budget = annealing.iterations / chains + (c < annealing.iterations % chains) as usize;
+                for i in 0..budget {
+                    let elapsed = started.elapsed();
+                    let progress = match annealing.time {
+                        Some(time) if elapsed >= time => break,
+                        Some(time) => elapsed.as_secs_f64() / time.as_secs_f64(),
+                        None => 0.0,
+                    };
+                    let progress = progress.max(i as f64 / budget as f64);
+
+                    let candidate = self.neighbour(&walker.strip, &mut rng);
+                    walker.proposed += 1;
+                    let candidate_score = match walker.seen.get(&candidate) {
+                        Some(&score) => score,
+                        None => {
+                            let score = score(&candidate)?;
+                            walker.seen.insert(candidate.clone(), score);
+                            score
+                        }
+                    };
+
+                    let candidate_energy = energy(candidate_score, objective);
+                    let threshold = (walker.energy - candidate_energy) / (temperature * (1.0 - progress));
+                    if candidate_energy <= walker.energy || rng.gen::<f64>() < threshold.exp() {
+                        if better(objective, candidate_score, walker.best.1) {
+                            walker.best = (candidate.clone(), candidate_score);
+                        }
+                        (walker.strip, walker.energy) = (candidate, candidate_energy);
+                        walker.accepted += 1;
+                    }
+                }
+
+                Ok((walker.best.0, walker.best.1, walker.proposed, walker.accepted))
+            })
+            .collect::<Result<_>>()?;
+
+        let proposed: usize = results.iter().map(|&(_, _, proposed, _)| proposed).sum();
+        let accepted: usize = results.iter().map(|&(_, _, _, accepted)| accepted).sum();
+        debug!("accepted {accepted} of {proposed} proposals");
+
+        let best = results
+            .into_iter()
+            .map(|(strip, score, _, _)| (strip, score))
+            .reduce(|best, candidate| match better(objective, candidate.1, best.1) {
+                true => candidate,
+                _ => best,
+            })
+            .and_then(|(strip, score)| Some((strip, score?)))
+            .map(|(strip, score)| CeremonyScore {
+                couples: self
+                    .strip_of(&strip)
+                    .into_iter()
+                    .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
+                    .collect(),
+                score,
+            });
+
+        Ok(best.into_iter().collect())
+    }
+
+    /// Picks a random ceremony to start a local search from, which is the ceremony of a random remaining world if there is
+    /// one to pick from, or a random seating otherwise.
+    fn random_ceremony(&self, worlds: &[usize], rng: &mut StdRng) -> Vec<usize> {
+        if let (Some(view), Some(&k)) = (self.worldview.as_ref(), worlds.choose(rng)) {
+            return self.ceremony_of(unsafe { view.get_unchecked(k) });
+        }
+
+        let (rows, cols) = (self.m.len(), self.f.len());
+        let mut strip = vec![NO_MATCH; rows];
+        match self.kind {
+            SeasonKind::Bipartite => {
+                let mut seats: Vec<usize> = (0..cols).collect();
+                seats.shuffle(rng);
+                index::sample(rng, rows, cols)
+                    .into_iter()
+                    .zip(seats)
+                    .for_each(|(a, b)| strip[a] = b);
+            }
+            SeasonKind::NonBipartite => {
+                let mut people: Vec<usize> = (0..rows).collect();
+                people.shuffle(rng);
+                people
+                    .chunks_exact(2)
+                    .for_each(|pair| strip[pair[0].min(pair[1])] = pair[0].max(pair[1]));
+            }
+        }
+        strip
+    }
+
+    /// Proposes a ceremony next to the given one, which is a single swap away from it, drawn uniformly from those that
+    /// `swaps` lists without listing them.
+    fn neighbour(&self, strip: &[usize], rng: &mut StdRng) -> Vec<usize> {
+        let rows = strip.len();
+        let seated = (0..rows).filter(|&a| strip[a] != NO_MATCH).collect_vec();
+        match self.kind {
+            SeasonKind::Bipartite if !seated.is_empty() && rows >= 2 => loop {
+                // Swapping two rows that are not seated at all changes nothing, so those are drawn again.
+                let (a, b) = (rng.gen_range(0..rows), rng.gen_range(0..rows));
+                if a != b && (strip[a] != NO_MATCH || strip[b] != NO_MATCH) {
+                    let mut swapped = strip.to_vec();
+                    swapped.swap(a, b);
+                    return swapped;
+                }
+            },
+            SeasonKind::NonBipartite if seated.len() >= 2 => {
+                let seats = index::sample(rng, seated.len(), 2);
+                repaired(strip, (seated[seats.index(0)], seated[seats.index(1)]), rng.gen())
+            }
+            _ => strip.to_vec(),
+        }
+    }
+}
+
+/// Converts a score into an energy for the search to minimize, where missing scores are infinitely bad.
+fn energy(score: Option<f64>, objective: Objective) -> f64 {
+    match (score, objective) {
+        (None, _) => f64::INFINITY,
+        (Some(score), Objective::Minimax | Objective::FollowUp) => score,
+        (Some(score), _) => -score,
+    }
+}
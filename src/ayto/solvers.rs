@@ -1,193 +1,544 @@
-use std::usize;
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use indicatif::{ProgressBar, ProgressStyle};
 use ndarray::parallel::prelude::IntoParallelRefIterator;
 
+use super::symmetry;
 use crate::prelude::*;
 
-const INDICATIF_TEMPLATE: &'static str =
+/// The most line-ups that the search for the best ceremony scores when it considers every line-up at all.
+const MAX_ALL_CANDIDATES: usize = 1_000_000;
+
+/// The most consistent worlds whose line-ups seed the search for the best ceremony when the worlds are not materialised.
+const MAX_LAZY_CANDIDATES: usize = 1_000;
+
+const INDICATIF_TEMPLATE: &str =
     " {bar:50} | {percent:>3}% ({human_pos}/{human_len}) | {eta_precise} remaining ({per_sec:<}) ";
 
-const RESOLUTION: f32 = 100.0;
+/// The remaining worlds of a season, bucketed by the number of beams that they would light up for some ceremony.
+pub(super) struct Histogram {
+    /// The number of worlds in each bucket.
+    pub(super) sizes: Vec<usize>,
+    /// The number of worlds in each bucket in which each seated couple is a perfect match, indexed by `[beams, m]`.
+    hits: Array2<usize>,
+    /// The number of worlds across all buckets.
+    total: usize,
+}
+
+impl Histogram {
+    /// Bounds the follow-up score of the ceremony that produced this histogram from below, using only the size of each
+    /// bucket.
+    ///
+    /// Whatever the outcome of a follow-up truth booth, at least half of the worlds in its bucket remain, so no score can
+    /// be lower than half of the largest bucket. Buckets only grow as more worlds are counted, so neither can the bound.
+    fn bound(&self) -> usize {
+        self.sizes.iter().map(|size| size.div_ceil(2)).max().unwrap_or(0)
+    }
+
+    /// Counts the most worlds that could remain in a bucket after the best follow-up truth booth on one of the
+    /// ceremony's own couples, unless it seats nobody.
+    ///
+    /// Both outcomes of a truth booth only gain worlds as more worlds are counted, so this never shrinks either, and
+    /// bounds the follow-up score from below at any point of a scan.
+    fn worst(&self, ceremony: &[usize], beams: usize) -> Option<usize> {
+        let size = self.sizes[beams];
+        ceremony
+            .iter()
+            .enumerate()
+            .filter(|(_, &f)| f != NO_MATCH)
+            .map(|(m, _)| {
+                let hits = self.hits[(beams, m)];
+                hits.max(size - hits)
+            })
+            .min()
+    }
+
+    /// Scales a histogram of a sample of worlds up to `total` worlds, so that it estimates the histogram of every world
+    /// that the sample was drawn from.
+    pub(super) fn scaled(self, total: usize) -> Histogram {
+        if self.total == 0 || self.total == total {
+            return self;
+        }
+
+        // Rounding is monotone, so no scaled hit count can outgrow the scaled size of its bucket.
+        let factor = total as f64 / self.total as f64;
+        let scale = |count: usize| (count as f64 * factor).round() as usize;
+        Histogram {
+            sizes: self.sizes.into_iter().map(scale).collect(),
+            hits: self.hits.mapv(scale),
+            total,
+        }
+    }
+
+    /// Scores the ceremony that produced this histogram in the units of an objective, unless there is nothing to score.
+    ///
+    /// The follow-up score is the most worlds that could remain after the best follow-up truth booth on one of the
+    /// ceremony's own couples, whatever the outcome, and is the only score that needs the hits of each seat. The others
+    /// only depend on the size of each bucket: the largest bucket, the entropy of the outcome in bits, the fewest beams
+    /// that could light up, and the expected number of beams.
+    pub(super) fn score(&self, ceremony: &[usize], objective: Objective) -> Option<f64> {
+        let mut buckets = self.sizes.iter().copied().enumerate().filter(|&(_, size)| size != 0).peekable();
+        buckets.peek()?;
+
+        match objective {
+            Objective::Minimax => buckets.map(|(_, size)| size).max().map(|size| size as f64),
+            Objective::FollowUp => buckets
+                .filter_map(|(beams, _)| self.worst(ceremony, beams))
+                .max()
+                .map(|worst| worst as f64),
+            Objective::Information => Some(entropy(self.sizes.iter().copied())),
+            Objective::Blackout => buckets.map(|(beams, _)| beams as f64).next(),
+            // The total is the same for every candidate, so equal sums tie exactly.
+            Objective::Beams => Some(buckets.map(|(beams, size)| beams * size).sum::<usize>() as f64 / self.total as f64),
+        }
+    }
+}
 
-impl<'a> Season<'a> {
-    pub fn find_best_ceremony(&self, naive: bool) -> Result<Vec<Vec<CoupleOutput>>> {
+/// Determines whether one score is better than another under an objective, where minimax and follow-up scores are
+/// minimized and the rest are maximized, and where any score is better than none.
+pub(super) fn better(objective: Objective, score: Option<f64>, than: Option<f64>) -> bool {
+    match (score, than, objective) {
+        (None, _, _) => false,
+        (_, None, _) => true,
+        (Some(score), Some(than), Objective::Minimax | Objective::FollowUp) => score < than,
+        (Some(score), Some(than), _) => score > than,
+    }
+}
+
+/// Trades the partners of the couples seated in two rows of a single pool, in one of the two ways that pair the four
+/// contestants up anew: the two rows together unless `crossed`, and each row with the other row's partner if it is.
+pub(super) fn repaired(strip: &[usize], (a, c): (usize, usize), crossed: bool) -> Vec<usize> {
+    let (b, d) = (strip[a], strip[c]);
+    let pairs = match crossed {
+        true => [(a, d), (b, c)],
+        _ => [(a, c), (b, d)],
+    };
+
+    // Ceremonies record each couple against its lower id.
+    let mut swapped = strip.to_vec();
+    (swapped[a], swapped[c]) = (NO_MATCH, NO_MATCH);
+    pairs.into_iter().for_each(|(x, y)| swapped[x.min(y)] = x.max(y));
+    swapped
+}
+
+/// Computes the expected number of bits that an outcome reveals, given the number of worlds that lead to each outcome.
+///
+/// Equal distributions only tie exactly if their sizes are listed in the same order.
+fn entropy(sizes: impl Iterator<Item = usize> + Clone) -> f64 {
+    let total = sizes.clone().sum::<usize>() as f64;
+    -sizes
+        .filter(|&size| size != 0)
+        .map(|size| size as f64 / total)
+        .map(|p| p * p.log2())
+        .sum::<f64>()
+}
+
+impl Season {
+    /// Finds the best ceremonies among the given candidate line-ups under an objective, by scoring every one of them.
+    ///
+    /// A line-up that has already been ruled out can still split the remaining worlds better than any line-up that could
+    /// be the perfect one, so the candidates may go beyond the line-ups of the remaining worlds; use `is_possible` to
+    /// check whether the best ones still could be. Line-ups that only differ by relabeling contestants whom the evidence
+    /// cannot tell apart are scored once, and share their score.
+    ///
+    /// While only truth booths are known, every candidate is scored analytically, so the worlds need not be materialised.
+    /// The line-ups of the consistent worlds are then taken from the enumerator instead, or from a sample of at most
+    /// `MAX_LAZY_CANDIDATES` of them if there are more.
+    pub fn find_best_ceremony(&self, objective: Objective, candidates: Candidates) -> Result<Vec<CeremonyScore>> {
+        if !self.is_analytic() {
+            self.view()?;
+        }
+        if candidates == Candidates::All {
+            let all = match self.kind {
+                SeasonKind::Bipartite => worldview::count_all(self.m.len(), self.f.len()),
+                SeasonKind::NonBipartite => worldview::count_matchings(self.m.len()),
+            };
+            if all > MAX_ALL_CANDIDATES {
+                return Err(anyhow!("can only score every line-up when there are at most {MAX_ALL_CANDIDATES}; use swaps instead"));
+            }
+        }
         let best = self
-            .find_best_ceremony_impl(naive)
-            .0
+            .find_best_ceremony_impl(objective, candidates)
             .into_iter()
-            .map(|v| {
-                v.into_iter()
+            .map(|(v, score)| CeremonyScore {
+                couples: v
+                    .into_iter()
                     .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
-                    .collect()
+                    .collect(),
+                score,
             })
             .collect();
 
         Ok(best)
     }
 
-    fn find_best_ceremony_impl(&self, naive: bool) -> (Vec<Vec<Couple>>, usize) {
-        if self.worlds.len() == 0 {
-            return (vec![], usize::MAX);
+    pub(super) fn find_best_ceremony_impl(&self, objective: Objective, candidates: Candidates) -> Vec<(Vec<Couple>, f64)> {
+        // Unmaterialised worlds are only ever scored analytically, so the worlds that seed the candidates stand in for them.
+        let (view, worlds): (Arc<Worldview>, Vec<usize>) = match &self.worldview {
+            Some(view) => (view.clone(), self.worlds.iter().collect()),
+            None if self.is_analytic() => {
+                let seeds = self.seed_worlds();
+                let worlds = (0..seeds.len()).collect();
+                (Arc::new(seeds), worlds)
+            }
+            None => return vec![],
+        };
+
+        if worlds.is_empty() {
+            return vec![];
         }
+        let view = view.as_ref();
+
+        // Every candidate is the line-up of some world, or is listed on its own.
+        let (all, swaps) = match candidates {
+            Candidates::Worlds => (None, vec![]),
+            Candidates::Swaps(k) => (None, self.swaps_of(view, &worlds, k)),
+            Candidates::All => (Some(Enumerator::new(self.kind, self.m.len(), self.f.len()).enumerate()), vec![]),
+        };
+        let keys: Vec<usize> = match (&all, candidates) {
+            (Some(all), _) => (0..all.len()).collect(),
+            (_, Candidates::Swaps(_)) => (0..swaps.len()).collect(),
+            _ => worlds.clone(),
+        };
+        let candidate = |k: usize| match (&all, candidates) {
+            (Some(all), _) => self.ceremony_of(unsafe { all.get_unchecked(k) }),
+            (_, Candidates::Swaps(_)) => swaps[k].clone(),
+            _ => self.ceremony_of(unsafe { view.get_unchecked(k) }),
+        };
 
-        let results: Vec<(Vec<Couple>, usize)> = if cfg!(feature="bench") {
-            let bar = ProgressBar::new(self.worlds.len() as u64).with_style(ProgressStyle::with_template(INDICATIF_TEMPLATE).unwrap());
-            
-            let r = self
-                .worlds
+        // Candidates that the evidence cannot tell apart score the same, so only the first one of each orbit is scored.
+        let generators = self.automorphisms();
+        let orbit = match generators.is_empty() {
+            true => (0..keys.len()).collect_vec(),
+            _ => {
+                let packed = keys.iter().map(|&k| worldview::pack(&candidate(k))).collect_vec();
+                symmetry::orbits(&packed, &generators, |strip, relabeling| {
+                    worldview::pack(&self.relabel_strip(&worldview::unpack(strip), relabeling))
+                })
+            }
+        };
+        let representatives = (0..keys.len()).filter(|&i| orbit[i] == i).collect_vec();
+
+        // Every worker shares the best minimax or follow-up score so far, and abandons any candidate as soon as it cannot
+        // beat it.
+        let (best, pruned) = (AtomicUsize::new(usize::MAX), AtomicUsize::new(0));
+        let score = |i: usize| {
+            let candidate = candidate(keys[i]);
+            if ![Objective::Minimax, Objective::FollowUp].contains(&objective) {
+                return self.histogram_of(&candidate, &worlds, false).ok()?.score(&candidate, objective);
+            }
+
+            let seats = objective == Objective::FollowUp;
+            let Some(histogram) = self.bounded_histogram(&candidate, &worlds, seats, &best).ok()? else {
+                pruned.fetch_add(1, Ordering::Relaxed);
+                return None;
+            };
+
+            let score = histogram.score(&candidate, objective);
+            if let Some(score) = score {
+                best.fetch_min(score as usize, Ordering::Relaxed);
+            }
+            score
+        };
+
+        let scores: Vec<Option<f64>> = if cfg!(feature="bench") {
+            let bar = ProgressBar::new(representatives.len() as u64).with_style(ProgressStyle::with_template(INDICATIF_TEMPLATE).unwrap());
+            let r = representatives
                 .iter()
-                .map(|&k| {
-                    let candidate = unsafe { self.worldview.get_unchecked(k) };
-                    let strip = candidate
-                        .clone()
-                        .into_iter()
-                        .enumerate()
-                        .collect::<Vec<Couple>>();
-    
-                    // We should iterate over beam values starting at the number of already-found couples,
-                    // because no world left in the worldview couild possibly match on fewer.
-                    let score = if naive {
-                        (self.found..=self.n)
-                            .map(|beams| {
-                                let nw = self.num_worlds() as f32;
-                                let mut season = self.clone();
-                                beams as f32 * RESOLUTION * season.apply_ceremony_impl(candidate, beams)
-                                    .and_then(|season| season.recalculate())
-                                    .map(|s| s.find_best_truth_impl(Some(&strip)).1 as f32 / nw)
-                                    .unwrap_or(0.0)
-                            })
-                            .sum::<f32>().round() as usize
-                    } else { 
-                        (self.found..=self.n)
-                            .map(|beams| {
-                                let mut season = self.clone();
-                                season
-                                    .apply_ceremony_impl(candidate, beams)
-                                    .and_then(|season| season.recalculate())
-                                    .map(|season| season.find_best_truth_impl(Some(&strip)).1)
-                                    .unwrap_or(usize::MAX)
-                            })
-                            .filter(|s| ![0, usize::MAX].contains(s))
-                            .min()
-                            .unwrap_or(usize::MAX)
-                    };
-    
+                .map(|&i| {
+                    let result = score(i);
                     bar.inc(1);
-                    (strip, score)
+                    result
                 })
                 .collect();
 
-                bar.finish_and_clear();
-                r
+            bar.finish_and_clear();
+            r
         } else {
-            self
-                .worlds
+            representatives
                 .par_iter()
                 .progress()
                 .with_style(ProgressStyle::with_template(INDICATIF_TEMPLATE).unwrap())
-                .map(|&k| {
-                    let candidate = unsafe { self.worldview.get_unchecked(k) };
-                    let strip = candidate
-                        .clone()
-                        .into_iter()
-                        .enumerate()
-                        .collect::<Vec<Couple>>();
-    
-                    // We should iterate over beam values starting at the number of already-found couples,
-                    // because no world left in the worldview couild possibly match on fewer.
-                    let score = if naive {
-                        (self.found..=self.n)
-                            .map(|beams| {
-                                let nw = self.num_worlds() as f32;
-                                let mut season = self.clone();
-                                beams as f32 * RESOLUTION * season.apply_ceremony_impl(candidate, beams)
-                                    .and_then(|season| season.recalculate())
-                                    .map(|s| s.num_worlds() as f32 / nw)
-                                    .unwrap_or(0.0)
-                            })
-                            .sum::<f32>().round() as usize
-                    } else { 
-                        (self.found..=self.n)
-                            .map(|beams| {
-                                let mut season = self.clone();
-                                season
-                                    .apply_ceremony_impl(candidate, beams)
-                                    .and_then(|season| season.recalculate())
-                                    .map(|season| season.find_best_truth_impl(Some(&strip)).1)
-                                    .unwrap_or(usize::MAX)
-                            })
-                            .filter(|s| ![0, usize::MAX].contains(s))
-                            .min()
-                            .unwrap_or(usize::MAX)
-                    };
-    
-                    (strip, score)
-                })
+                .map(|&i| score(i))
                 .collect()
         };
+        debug!(
+            "scored {} orbits of {} candidates, and pruned {} of them",
+            representatives.len(),
+            keys.len(),
+            pruned.into_inner()
+        );
 
-        let best_score = if naive {
-            results
-                .iter()
-                .map(|(_, score)| score)
-                .filter(|score| ![0, usize::MAX].contains(score))
-                .max()
-                .cloned()            
-        } else { 
-            results
-                .iter()
-                .map(|(_, score)| score)
-                .filter(|score| ![0, usize::MAX].contains(score))
-                .min()
-                .cloned()
-        };
+        // Every candidate takes the score of its orbit, so that ties are listed just as if each had been scored.
+        let mut scored = vec![None; keys.len()];
+        representatives.iter().zip(scores).for_each(|(&i, score)| scored[i] = score);
 
-        let Some(best_score) = best_score else {
-            return (vec![], usize::MAX);
+        let Some(best_score) = scored.iter().fold(None, |best, &score| match better(objective, score, best) {
+            true => score,
+            _ => best,
+        }) else {
+            return vec![];
         };
 
-        let best: Vec<Vec<Couple>> = results
-            .into_iter()
-            .filter(|(_, score)| ![0, usize::MAX].contains(score))
-            .filter_map(|(candidate, score)| {
-                if score == best_score {
-                    Some(candidate)
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        (best, best_score)
+        keys.iter()
+            .enumerate()
+            .filter(|&(i, _)| scored[orbit[i]] == Some(best_score))
+            .map(|(_, &k)| (self.strip_of(&candidate(k)), best_score))
+            .collect()
     }
 
+    /// Finds the best couples to send to the truth booth, optionally among the given couples only, along with the score of
+    /// each in the units of every objective.
+    ///
+    /// A truth booth only has two outcomes, and the couple that leaves the fewest worlds in the worst case is also the one
+    /// whose outcome is closest to a coin flip, so both objectives always pick the same couples; the expected number of
+    /// bits is still worth reporting, since it is comparable with that of a ceremony.
     pub fn find_best_truth(
         &self,
         constraint: Option<&Vec<CoupleInput>>,
-    ) -> Result<Vec<CoupleOutput>> {
+        objective: Objective,
+    ) -> Result<Vec<TruthScore>> {
+        if self.engine().is_none() {
+            return Err(anyhow!("cannot count these worlds exactly; use `materialise` first"));
+        }
+        if ![Objective::Minimax, Objective::Information].contains(&objective) {
+            return Err(anyhow!("truth booths have no beams or follow-up; use the minimax or information objective"));
+        }
+
         let constraint: Option<Vec<Couple>> = constraint
             .map(|o| {
                 o.iter()
-                    .map(|(m, f)| {
-                        Ok::<Couple, Error>((self.id(m, &self.ms)?, self.id(f, &self.fs)?))
-                    })
+                    .map(|&couple| Ok(self.canonical(self.couple_id(couple)?)))
                     .try_collect::<Couple, Vec<Couple>, Error>()
             })
             .transpose()?;
 
         let result = self
-            .find_best_truth_impl(constraint.as_ref())
-            .0
+            .find_best_truth_impl(constraint.as_ref(), objective)
             .into_iter()
-            .map(|(m, f)| (self.m[m].clone(), self.f[f].clone()))
+            .map(|((m, f), worst, bits)| TruthScore {
+                couple: (self.m[m].clone(), self.f[f].clone()),
+                worst,
+                bits,
+            })
             .collect();
 
         Ok(result)
     }
 
-    fn find_best_truth_impl(&self, constraint: Option<&Vec<Couple>>) -> (Vec<Couple>, usize) {
-        let all_couples: Vec<Couple> = (0..self.n).cartesian_product(0..self.n).collect();
+    /// Determines whether a ceremony could still light up every beam, that is, whether it could be the perfect line-up.
+    ///
+    /// This always checks against the evidence itself, so it is exact even when the season is only sampled.
+    pub fn is_possible(&self, couples: &[CoupleInput]) -> Result<bool> {
+        let strip = self.strip(couples)?;
+        let mut enumerator = self.enumerator();
+        enumerator.constrain(&Evidence::Ceremony(strip, self.n));
+        Ok(enumerator.first().is_some())
+    }
+
+    /// Collects the consistent worlds whose line-ups are the candidates of an unmaterialised season: every one of them if
+    /// there are at most `MAX_LAZY_CANDIDATES`, and a sample of that many otherwise, without repeats.
+    fn seed_worlds(&self) -> Worldview {
+        let enumerator = self.enumerator();
+        if let Some(view) = enumerator.enumerate_up_to(MAX_LAZY_CANDIDATES) {
+            return view;
+        }
+
+        let sample = Sampler::new(self.kind, enumerator).sample(MAX_LAZY_CANDIDATES, 0);
+        let mut seeds = Worldview::new(self.m.len());
+        sample.iter().unique().for_each(|world| seeds.push(&worldview::unpack(world)));
+        seeds
+    }
+
+    /// Lists every line-up within `k` swaps of the line-up of one of the given worlds, without repeats.
+    fn swaps_of(&self, view: &Worldview, worlds: &[usize], k: usize) -> Vec<Vec<usize>> {
+        let mut seen: HashSet<Vec<usize>> = worlds
+            .iter()
+            .map(|&w| self.ceremony_of(unsafe { view.get_unchecked(w) }))
+            .collect();
+
+        let mut frontier: Vec<Vec<usize>> = seen.iter().cloned().collect();
+        for _ in 0..k {
+            frontier = frontier
+                .iter()
+                .flat_map(|strip| self.swaps(strip))
+                .filter(|strip| seen.insert(strip.clone()))
+                .collect();
+        }
+
+        seen.into_iter().sorted().collect()
+    }
+
+    /// Lists every line-up that is a single swap away from the given one.
+    ///
+    /// A swap trades the partners of two seated couples, or, in a bipartite season, moves a seat to a row that was not
+    /// seated at all.
+    pub(super) fn swaps(&self, strip: &[usize]) -> Vec<Vec<usize>> {
+        let rows = strip.len();
+        let seated = |a: usize| strip[a] != NO_MATCH;
+        match self.kind {
+            SeasonKind::Bipartite => (0..rows)
+                .filter(|&a| seated(a))
+                .flat_map(|a| (0..rows).filter(move |&b| b != a && !(seated(b) && b < a)).map(move |b| (a, b)))
+                .map(|(a, b)| {
+                    let mut swapped = strip.to_vec();
+                    swapped.swap(a, b);
+                    swapped
+                })
+                .collect(),
+            SeasonKind::NonBipartite => (0..rows)
+                .filter(|&a| seated(a))
+                .tuple_combinations()
+                .flat_map(|seats| [false, true].map(|crossed| repaired(strip, seats, crossed)))
+                .collect(),
+        }
+    }
+
+    /// Converts a match strip into the list of couples that sit together.
+    pub(super) fn strip_of(&self, candidate: &[usize]) -> Vec<Couple> {
+        candidate
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|&(_, f)| f != NO_MATCH)
+            .collect()
+    }
+
+    /// Buckets the given worlds by the number of beams they would light up for a ceremony, analytically if possible and
+    /// by scanning them otherwise. The hits of each seated couple are only counted if `seats` is set.
+    ///
+    /// Errors if the season would have to be scanned, but has not been materialised.
+    pub(super) fn histogram_of(&self, ceremony: &[usize], worlds: &[usize], seats: bool) -> Result<Histogram> {
+        match self.analytic_histogram(ceremony, worlds.len(), seats) {
+            Some(histogram) => Ok(histogram),
+            None => Ok(self
+                .histogram(self.view()?, ceremony, worlds, seats, &AtomicUsize::new(usize::MAX))
+                .expect("no bucket outgrows an unbounded score")),
+        }
+    }
+
+    /// Buckets the given worlds like `histogram_of`, unless the ceremony's minimax score, or its follow-up score if
+    /// `seats` is set, is sure to be worse than `best`.
+    ///
+    /// Analytic histograms are dropped when the size of their buckets alone rules out their follow-up score, and scans
+    /// give up as soon as any bucket is too large, which gets easier as other candidates lower `best` in the meantime.
+    fn bounded_histogram(
+        &self,
+        ceremony: &[usize],
+        worlds: &[usize],
+        seats: bool,
+        best: &AtomicUsize,
+    ) -> Result<Option<Histogram>> {
+        match self.analytic_histogram(ceremony, worlds.len(), seats) {
+            Some(histogram) if seats && histogram.bound() > best.load(Ordering::Relaxed) => Ok(None),
+            Some(histogram) => Ok(Some(histogram)),
+            None => Ok(self.histogram(self.view()?, ceremony, worlds, seats, best)),
+        }
+    }
+
+    /// Buckets the given worlds by the number of beams they would light up for a ceremony, in a single pass. The hits of
+    /// each seated couple are only counted if `seats` is set.
+    ///
+    /// Gives up as soon as the score of the worlds counted so far passes `best`, since it could then never beat it: the
+    /// follow-up score if `seats` is set, and the minimax score otherwise.
+    fn histogram(
+        &self,
+        view: &Worldview,
+        ceremony: &[usize],
+        worlds: &[usize],
+        seats: bool,
+        best: &AtomicUsize,
+    ) -> Option<Histogram> {
+        let packed = worldview::pack(ceremony);
+        let mut histogram = Histogram {
+            sizes: vec![0; self.n + 1],
+            hits: Array2::zeros((self.n + 1, self.m.len())),
+            total: worlds.len(),
+        };
+
+        for &k in worlds {
+            let world = unsafe { view.get_unchecked(k) };
+            let beams = worldview::beams(world, &packed);
+            histogram.sizes[beams] += 1;
+            if seats {
+                histogram
+                    .hits
+                    .row_mut(beams)
+                    .iter_mut()
+                    .zip(world.iter().zip(&packed))
+                    .for_each(|(hits, (lhs, rhs))| *hits += (lhs == rhs) as usize);
+            }
+
+            // Only the bucket that just grew can have raised the score, and never past its own size.
+            let best = best.load(Ordering::Relaxed);
+            if histogram.sizes[beams] <= best {
+                continue;
+            }
+            if !seats || histogram.worst(ceremony, beams).is_some_and(|worst| worst > best) {
+                return None;
+            }
+        }
+
+        Some(histogram)
+    }
+
+    /// Buckets every remaining world by the number of beams it would light up for a ceremony without touching any of
+    /// them, provided that only truth booths have been recorded so far and that this is cheaper than scanning `worlds`.
+    ///
+    /// The buckets are counted with a rook polynomial over the couples that the truth booths still allow. If `seats` is
+    /// set, each seated couple's hits are counted the same way once that couple is forced to be a perfect match, and
+    /// otherwise they are left empty.
+    fn analytic_histogram(&self, ceremony: &[usize], worlds: usize, seats: bool) -> Option<Histogram> {
+        if !self.is_analytic() {
+            return None;
+        }
+
+        // Each polynomial takes about n^2 steps per state, and there is one for the ceremony and maybe one for every seat.
+        let (rows, cols) = (self.m.len(), self.f.len());
+        let polynomials = if seats { self.n + 1 } else { 1 };
+        let cost = permanent::states(self.kind, rows, cols)?.saturating_mul(self.n.pow(2) * polynomials);
+        if self.worldview.is_some() && cost >= worlds {
+            return None;
+        }
+
+        let spread = |allowed: &Array2<bool>| {
+            let spread = match self.kind {
+                SeasonKind::Bipartite => permanent::spread_bipartite(allowed, ceremony),
+                SeasonKind::NonBipartite => permanent::spread_pool(allowed, ceremony),
+            };
+            spread.map(|spread| (0..=self.n).map(|k| spread.get(k).copied().unwrap_or(0) as usize).collect_vec())
+        };
+
+        let allowed = self.enumerator().allowed_couples();
+        let sizes = spread(&allowed)?;
+        let mut hits = Array2::zeros((self.n + 1, rows));
+        for (m, &f) in ceremony.iter().enumerate().filter(|(_, &f)| seats && f != NO_MATCH) {
+            // In a single pool, both contestants of a forced couple are taken off the market, in either orientation.
+            let pool = self.kind == SeasonKind::NonBipartite;
+            let forced = Array2::from_shape_fn((rows, cols), |(a, b)| {
+                let couple = (a, b) == (m, f) || (pool && (b, a) == (m, f));
+                let taken = a == m || (pool && [m, f].iter().any(|&c| [a, b].contains(&c)));
+                allowed[(a, b)] && (couple || !taken)
+            });
+            hits.column_mut(m).assign(&Array1::from(spread(&forced)?));
+        }
+
+        let total = sizes.iter().sum();
+        Some(Histogram { sizes, hits, total })
+    }
+
+    /// Determines whether ceremonies can be bucketed with a rook polynomial, which takes exact counts, and only truth
+    /// booths among the evidence.
+    fn is_analytic(&self) -> bool {
+        let truths = self.evidence.iter().all(|evidence| matches!(evidence, Evidence::Truth(..)));
+        self.sampling.is_none() && truths && permanent::states(self.kind, self.m.len(), self.f.len()).is_some()
+    }
+
+    pub(super) fn find_best_truth_impl(&self, constraint: Option<&Vec<Couple>>, objective: Objective) -> Vec<(Couple, usize, f64)> {
+        let all_couples: Vec<Couple> = self.couples();
 
         let candidates = if let Some(constraint) = constraint {
             constraint
@@ -196,56 +547,49 @@ impl<'a> Season<'a> {
             &all_couples
         };
 
-        if self.worlds.len() == 0 || candidates.len() == 0 {
-            return (vec![], usize::MAX);
+        if self.num_worlds() == 0 || candidates.is_empty() {
+            return vec![];
         }
 
+        // Couples that the evidence cannot tell apart score the same, so only the first one of each orbit is scored.
+        let orbit = symmetry::orbits(candidates, &self.automorphisms(), |&couple, relabeling| {
+            self.relabel_couple(couple, relabeling)
+        });
+
+        let nw = self.num_worlds();
+        let mut scored = vec![(usize::MAX, 0.0); candidates.len()];
         let results = candidates
             .iter()
-            .map(|candidate| {
-                // If a couple is already determined to either be or not be a couple, give it a marker.
-                let prior = self.distribution[*candidate];
-                let worlds = (prior * self.num_worlds() as f32) as usize;
-                let worst = worlds.max(self.num_worlds() - worlds);
-
-                if worst == usize::MAX {
-                    return (candidate, usize::MAX);
+            .enumerate()
+            .map(|(i, candidate)| {
+                if orbit[i] != i {
+                    let (worst, bits) = scored[orbit[i]];
+                    return (candidate, worst, bits);
                 }
 
-                (candidate, worst)
-            })
-            .collect::<Vec<(&Couple, usize)>>();
+                // The counts are exact and always current, so the worlds themselves never need to be touched.
+                let worlds = self.counts[*candidate] as usize;
+                let worst = worlds.max(nw - worlds);
+                let bits = entropy([nw - worst, worst].into_iter());
+                scored[i] = (worst, bits);
 
-        let lowest_worst_case = results
-            .iter()
-            // Drop incoherent worlds (which either have no worlds or have the marker usize::MAX to indicate explicit contradiction).
-            .filter_map(|(_, score)| {
-                if ![0, usize::MAX].contains(score) {
-                    Some(score)
-                } else {
-                    None
-                }
+                (candidate, worst, bits)
             })
-            .min()
-            .cloned();
+            .collect::<Vec<(&Couple, usize, f64)>>();
 
-        let Some(lowest_score) = lowest_worst_case else {
-            return (vec![], usize::MAX);
+        let best = |&(_, worst, bits): &(&Couple, usize, f64)| match objective {
+            Objective::Information => bits,
+            _ => -(worst as f64),
         };
 
-        let best: Vec<Couple> = results
-            .into_iter()
-            // Keep all those results
-            .filter_map(|(candidate, score)| {
-                if [0, usize::MAX].contains(&score) || score == lowest_score {
-                    Some(candidate)
-                } else {
-                    None
-                }
-            })
-            .cloned()
-            .collect();
+        let Some(best_score) = results.iter().map(best).max_by(f64::total_cmp) else {
+            return vec![];
+        };
 
-        (best, lowest_score)
+        results
+            .into_iter()
+            .filter(|result| best(result) == best_score)
+            .map(|(&candidate, worst, bits)| (candidate, worst, bits))
+            .collect()
     }
 }
@@ -0,0 +1,230 @@
+use std::{collections::HashSet, hash::Hash};
+
+use super::components::{find, union};
+use crate::prelude::*;
+
+/// A relabeling of the contestants, which sends every row and every column to another one.
+///
+/// In a single pool, rows and columns are the same contestants, so both are relabeled the same way.
+pub(super) struct Relabeling {
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+}
+
+impl Season {
+    /// Finds relabelings of the contestants that leave the recorded evidence exactly as it is, so that every candidate
+    /// scores the same as its image under any of them.
+    ///
+    /// Only swaps of two contestants on the same side, and pairs of such swaps, are considered, so the relabelings
+    /// generate a subgroup of every symmetry of the evidence; any two contestants that have never been told apart are
+    /// always caught, as are two couples that have only ever sat together. Swaps that the group already contains are
+    /// skipped, so that orbits stay cheap to walk. Sampled seasons have no symmetries, since their worlds are not.
+    pub(super) fn automorphisms(&self) -> Vec<Relabeling> {
+        if self.sampling.is_some() {
+            return vec![];
+        }
+
+        // Truth booths in a single pool can be recorded in either orientation, so they are all oriented canonically first.
+        let identity = self.relabeling(None, None);
+        let evidence: HashSet<Evidence> = self
+            .evidence
+            .iter()
+            .map(|piece| self.relabel_evidence(piece, &identity))
+            .collect();
+        let preserves = |relabeling: &Relabeling| {
+            self.evidence
+                .iter()
+                .all(|piece| evidence.contains(&self.relabel_evidence(piece, relabeling)))
+        };
+
+        let generators = self.symmetries(preserves);
+        debug!("found {} symmetries of the evidence", generators.len());
+        generators
+    }
+
+    /// Finds relabelings of the contestants that pass some test, among swaps of two contestants on the same side and pairs
+    /// of such swaps, skipping any swap that the relabelings found so far already generate.
+    pub(super) fn symmetries(&self, preserves: impl Fn(&Relabeling) -> bool) -> Vec<Relabeling> {
+        // Swaps that join two contestants who can already be swapped add nothing to the group.
+        let (rows, cols) = (self.m.len(), self.f.len());
+        let pool = self.kind == SeasonKind::NonBipartite;
+        let mut parents = ((0..rows).collect_vec(), (0..cols).collect_vec());
+        let swappable = |parents: &mut [usize], (a, b): (usize, usize)| find(parents, a) == find(parents, b);
+        let mut generators = vec![];
+
+        let swaps = |len: usize| (0..len).tuple_combinations::<(usize, usize)>().collect_vec();
+        let row_swaps = swaps(rows);
+        let col_swaps = if pool { vec![] } else { swaps(cols) };
+
+        for &swap in &row_swaps {
+            let relabeling = self.relabeling(Some(swap), None);
+            if !swappable(&mut parents.0, swap) && preserves(&relabeling) {
+                union(&mut parents.0, swap.0, swap.1);
+                generators.push(relabeling);
+            }
+        }
+        for &swap in &col_swaps {
+            let relabeling = self.relabeling(None, Some(swap));
+            if !swappable(&mut parents.1, swap) && preserves(&relabeling) {
+                union(&mut parents.1, swap.0, swap.1);
+                generators.push(relabeling);
+            }
+        }
+
+        // In a single pool, a pair of swaps moves one couple onto another, so both swaps come from the rows.
+        let pairs = match pool {
+            true => row_swaps
+                .iter()
+                .tuple_combinations()
+                .filter(|(a, b)| [a.0, a.1].iter().all(|c| ![b.0, b.1].contains(c)))
+                .map(|(&a, &b)| (a, b))
+                .collect_vec(),
+            _ => row_swaps.iter().cartesian_product(&col_swaps).map(|(&a, &b)| (a, b)).collect_vec(),
+        };
+        for (a, b) in pairs {
+            let joined = match pool {
+                true => (swappable(&mut parents.0, a), swappable(&mut parents.0, b)),
+                _ => (swappable(&mut parents.0, a), swappable(&mut parents.1, b)),
+            };
+            if joined == (true, true) {
+                continue;
+            }
+
+            let relabeling = match pool {
+                true => self.relabeling(Some(a), None).then(&self.relabeling(Some(b), None)),
+                _ => self.relabeling(Some(a), Some(b)),
+            };
+            if !preserves(&relabeling) {
+                continue;
+            }
+
+            // Once either swap is in the group, the other one is too, and it is enough to keep that one instead.
+            match (joined, pool) {
+                ((true, _), true) => {
+                    union(&mut parents.0, b.0, b.1);
+                    generators.push(self.relabeling(Some(b), None));
+                }
+                ((true, _), false) => {
+                    union(&mut parents.1, b.0, b.1);
+                    generators.push(self.relabeling(None, Some(b)));
+                }
+                ((_, true), _) => {
+                    union(&mut parents.0, a.0, a.1);
+                    generators.push(self.relabeling(Some(a), None));
+                }
+                _ => generators.push(relabeling),
+            }
+        }
+
+        generators
+    }
+
+    /// Builds the relabeling that swaps at most two rows and at most two columns, and otherwise leaves everyone in place.
+    fn relabeling(&self, rows: Option<(usize, usize)>, cols: Option<(usize, usize)>) -> Relabeling {
+        let swapped = |len: usize, swap: Option<(usize, usize)>| {
+            let mut labels = (0..len).collect_vec();
+            if let Some((a, b)) = swap {
+                labels.swap(a, b);
+            }
+            labels
+        };
+
+        let rows = swapped(self.m.len(), rows);
+        let cols = match self.kind {
+            SeasonKind::Bipartite => swapped(self.f.len(), cols),
+            SeasonKind::NonBipartite => rows.clone(),
+        };
+        Relabeling { rows, cols }
+    }
+
+    /// Relabels a couple, orienting it canonically.
+    pub(super) fn relabel_couple(&self, couple: Couple, relabeling: &Relabeling) -> Couple {
+        let (m, f) = couple;
+        self.canonical((relabeling.rows[m], relabeling.cols[f]))
+    }
+
+    /// Relabels a match strip, so that every couple that sits together is replaced by its image.
+    pub(super) fn relabel_strip(&self, strip: &[usize], relabeling: &Relabeling) -> Vec<usize> {
+        let mut relabeled = vec![NO_MATCH; strip.len()];
+        strip
+            .iter()
+            .enumerate()
+            .filter(|(_, &f)| f != NO_MATCH)
+            .map(|(m, &f)| self.relabel_couple((m, f), relabeling))
+            .for_each(|(m, f)| relabeled[m] = f);
+        relabeled
+    }
+
+    /// Relabels a world, so that every couple in it is replaced by its image.
+    pub(super) fn relabel_world(&self, world: &[u8], relabeling: &Relabeling) -> Vec<u8> {
+        let mut relabeled = vec![0; world.len()];
+        world
+            .iter()
+            .enumerate()
+            .for_each(|(m, &f)| relabeled[relabeling.rows[m]] = relabeling.cols[f as usize] as u8);
+        relabeled
+    }
+
+    /// Relabels a piece of evidence, keeping its outcome.
+    fn relabel_evidence(&self, evidence: &Evidence, relabeling: &Relabeling) -> Evidence {
+        match evidence {
+            Evidence::Truth(couple, correct) => Evidence::Truth(self.relabel_couple(*couple, relabeling), *correct),
+            Evidence::Ceremony(strip, beams) => Evidence::Ceremony(self.relabel_strip(strip, relabeling), *beams),
+        }
+    }
+}
+
+impl Relabeling {
+    /// Composes two relabelings, applying this one first.
+    fn then(&self, other: &Relabeling) -> Relabeling {
+        Relabeling {
+            rows: self.rows.iter().map(|&r| other.rows[r]).collect(),
+            cols: self.cols.iter().map(|&c| other.cols[c]).collect(),
+        }
+    }
+}
+
+/// Splits a list of items into the orbits of the group that the given relabelings generate, and maps each item to the
+/// first item in its orbit.
+///
+/// Images that are not in the list themselves are ignored, which can only split an orbit into more pieces than needed.
+pub(super) fn orbits<T: Hash + Eq>(
+    items: &[T],
+    generators: &[Relabeling],
+    relabel: impl Fn(&T, &Relabeling) -> T,
+) -> Vec<usize> {
+    if generators.is_empty() {
+        return (0..items.len()).collect();
+    }
+
+    // Repeated items are the same item, so each one is walked from its first occurrence only.
+    let mut ids: HashMap<&T, usize> = HashMap::new();
+    let firsts = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| *ids.entry(item).or_insert(i))
+        .collect_vec();
+
+    let mut orbit = vec![usize::MAX; items.len()];
+    for i in 0..items.len() {
+        if firsts[i] != i || orbit[i] != usize::MAX {
+            continue;
+        }
+
+        orbit[i] = i;
+        let mut stack = vec![i];
+        while let Some(j) = stack.pop() {
+            for generator in generators {
+                let Some(&k) = ids.get(&relabel(&items[j], generator)) else {
+                    continue;
+                };
+                if orbit[k] == usize::MAX {
+                    orbit[k] = i;
+                    stack.push(k);
+                }
+            }
+        }
+    }
+
+    firsts.into_iter().map(|first| orbit[first]).collect()
+}
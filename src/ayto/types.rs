@@ -1,3 +1,205 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
 pub type Couple = (usize, usize);
 pub type CoupleInput<'a> = (&'a str, &'a str);
 pub type CoupleOutput = (String, String);
+
+/// A marker in a match strip for a contestant who does not sit with anybody.
+pub const NO_MATCH: usize = usize::MAX;
+
+/// The shape of the matching that a season is played over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeasonKind {
+    /// Two groups, where everyone in the larger group has exactly one match in the smaller group.
+    Bipartite,
+    /// A single pool of contestants, where anyone can be matched with anyone else.
+    NonBipartite,
+}
+
+/// The method that a season uses to count its worlds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    /// Every remaining world has been materialised, and is counted directly.
+    Enumeration,
+    /// Only truth booths have been recorded, so worlds are counted by a dynamic program over sets of contestants.
+    Permanent,
+    /// The season splits into independent components, which are counted on their own and combined, meeting in the middle
+    /// for any that are too large to enumerate.
+    Components,
+    /// The season tracks a sample of its worlds instead, so every count is an estimate.
+    Sampling,
+}
+
+/// The settings that a season in approximate mode draws its sample of worlds with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sampling {
+    /// The number of worlds to draw after each piece of evidence.
+    pub samples: usize,
+    /// The seed that the chains are seeded from.
+    pub seed: u64,
+}
+
+/// The line-ups that the search for the best ceremony considers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Candidates {
+    /// Only the line-ups of the remaining worlds, each of which could still be the perfect line-up.
+    Worlds,
+    /// Every line-up within some number of swaps of the line-up of a remaining world.
+    Swaps(usize),
+    /// Every line-up at all, whether or not it could still be the perfect line-up.
+    All,
+}
+
+/// The quantity that the search for the best truth booth or ceremony optimises.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Objective {
+    /// The fewest worlds that could remain, whatever the outcome.
+    Minimax,
+    /// The fewest worlds that could remain after a ceremony and the best follow-up truth booth on one of its own couples,
+    /// whatever their outcomes.
+    FollowUp,
+    /// The most bits of information that the outcome is expected to reveal, that is, its Shannon entropy.
+    Information,
+    /// The most beams that a ceremony is guaranteed to light up, which rules out a blackout whenever one can be.
+    Blackout,
+    /// The most beams that a ceremony is expected to light up.
+    Beams,
+}
+
+/// A truth booth that has been scored by `find_best_truth`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TruthScore {
+    /// The couple that is sent to the truth booth.
+    pub couple: CoupleOutput,
+    /// The most worlds that could remain after the truth booth, whatever its outcome.
+    pub worst: usize,
+    /// The expected number of bits of information that the truth booth reveals.
+    pub bits: f64,
+}
+
+/// A ceremony that has been scored by `find_best_ceremony`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CeremonyScore {
+    /// The couples that sit together at the ceremony.
+    pub couples: Vec<CoupleOutput>,
+    /// The score of the ceremony, in the units of the objective that it was scored with.
+    pub score: f64,
+}
+
+/// A kind of move that a lookahead plans a sequence of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Move {
+    /// A single couple is sent to the truth booth.
+    Truth,
+    /// Everybody sits at a matching ceremony.
+    Ceremony,
+}
+
+/// A first move that has been looked ahead from by `lookahead`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    /// The couples that the first move sends to the truth booth or sits at the ceremony.
+    pub couples: Vec<CoupleOutput>,
+    /// Whether this is the move that the greedy solver would have picked on its own.
+    pub greedy: bool,
+    /// The expected number of worlds that remain after every move has been played.
+    pub expected: f64,
+    /// The most worlds that could remain after every move has been played, whatever the outcomes.
+    pub worst: usize,
+}
+
+/// A single move, along with the couples that it sends to the truth booth or sits at the ceremony.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Step {
+    /// The kind of move.
+    pub kind: Move,
+    /// The couples that the move involves.
+    pub couples: Vec<CoupleOutput>,
+}
+
+/// The value of the game from some state, as solved by `solve`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    /// The fewest moves that always identify the perfect line-up.
+    pub worst: usize,
+    /// A first move that identifies the perfect line-up within `worst` moves, unless it is already identified.
+    pub worst_step: Option<Step>,
+    /// The fewest moves that identify the perfect line-up on average over the remaining worlds.
+    pub expected: f64,
+    /// A first move that identifies the perfect line-up within `expected` moves on average, unless it is already
+    /// identified.
+    pub expected_step: Option<Step>,
+}
+
+/// The outcome of a single move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// A truth booth either was or was not a perfect match.
+    Match(bool),
+    /// A ceremony lit up some number of beams.
+    Beams(usize),
+}
+
+/// A node of a decision tree, as built by `decision_tree`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Decision {
+    /// The number of worlds that remain at this node.
+    pub worlds: usize,
+    /// The perfect line-up, once it is the only world that remains.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub solution: Option<Vec<CoupleOutput>>,
+    /// The move that is recommended at this node, unless the tree stops here.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<Step>,
+    /// Every outcome of the move that could happen, and the node that each one leads to.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<Branch>,
+}
+
+/// An edge of a decision tree, from a move to the node that one of its outcomes leads to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Branch {
+    /// The outcome that was observed.
+    pub outcome: Outcome,
+    /// The node that the outcome leads to.
+    pub decision: Decision,
+}
+
+/// The fewest truth booths that always identify the perfect line-up, as found by `truth_cover`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TruthCover {
+    /// A set of couples whose truth booths tell every remaining world apart, whatever their outcomes, which is the
+    /// smallest one if `minimal`.
+    pub couples: Vec<CoupleOutput>,
+    /// Whether the set is known to be the smallest, rather than the search giving up before it could tell.
+    pub minimal: bool,
+    /// The fewest truth booths that always identify the perfect line-up, when each one can depend on the outcomes of
+    /// the ones before it, unless solving that sequence gave up.
+    pub adaptive: Option<usize>,
+    /// A sequence of truth booths that always identifies the perfect line-up within `adaptive` of them.
+    pub tree: Option<Decision>,
+}
+
+/// The budget that a local search for the best ceremony runs with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Annealing {
+    /// The number of candidates that the search proposes, across every chain.
+    pub iterations: usize,
+    /// The time after which the search stops, even if it has not used up its iterations.
+    pub time: Option<Duration>,
+    /// The seed that the chains are seeded from.
+    pub seed: u64,
+}
+
+/// A piece of evidence that has been recorded against a season.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Evidence {
+    /// A couple was sent to the truth booth, and either was or was not a perfect match.
+    Truth(Couple, bool),
+    /// A match strip was sat at a ceremony, and lit up some number of beams.
+    Ceremony(Vec<usize>, usize),
+}
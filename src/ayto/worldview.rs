@@ -1,13 +1,465 @@
-/// Generates the kth permutation of the numbers 0..n.
+use std::fs::{self, File, OpenOptions};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::prelude::*;
+
+/// The packed representation of `NO_MATCH` in a byte-packed match strip.
+pub const PACKED_NO_MATCH: u8 = u8::MAX;
+
+/// A contiguous store of worlds, where every world is a byte-packed match strip of the same length.
+///
+/// Keeping every world in one buffer avoids an allocation per world, and lets beam counting run over plain bytes. That
+/// buffer is either owned in memory, or mapped from a cache on disk.
+#[derive(Clone, Debug, Default)]
+pub struct Worldview {
+    stride: usize,
+    data: Storage,
+    index: OnceLock<Index>,
+}
+
+/// The bytes behind a worldview.
+#[derive(Clone, Debug)]
+enum Storage {
+    /// The worlds are owned in memory.
+    Owned(Vec<u8>),
+    /// The worlds are mapped read-only from a file, so the operating system can page them in and out as needed.
+    Mapped(Arc<Mmap>),
+}
+
+impl Default for Storage {
+    fn default() -> Storage {
+        Storage::Owned(vec![])
+    }
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Storage::Owned(data) => data,
+            Storage::Mapped(map) => map,
+        }
+    }
+}
+
+impl Storage {
+    /// Returns the bytes as an owned buffer that can be extended, copying them out of the file if they are mapped.
+    fn owned(&mut self) -> &mut Vec<u8> {
+        if let Storage::Mapped(map) = self {
+            *self = Storage::Owned(map.to_vec());
+        }
+
+        match self {
+            Storage::Owned(data) => data,
+            Storage::Mapped(_) => unreachable!(),
+        }
+    }
+}
+
+/// An inverted index over a worldview, holding the set of worlds that contain each couple.
+///
+/// With this, filtering on a couple is a single bitset operation, and counting the worlds that contain a couple is a
+/// single popcount.
+#[derive(Clone, Debug)]
+pub struct Index {
+    columns: usize,
+    couples: Vec<Bitset>,
+}
+
+impl Index {
+    /// Builds the index for a worldview whose worlds match rows onto `columns` contestants, one row at a time.
+    fn build(view: &Worldview, columns: usize) -> Index {
+        let couples = (0..view.stride)
+            .into_par_iter()
+            .flat_map_iter(|m| {
+                let mut row = vec![Bitset::new(view.len()); columns];
+                view.iter()
+                    .enumerate()
+                    .for_each(|(k, world)| row[world[m] as usize].insert(k));
+                row
+            })
+            .collect();
+
+        Index { columns, couples }
+    }
+
+    /// Returns the set of worlds in which the given couple is a perfect match.
+    pub fn couple(&self, couple: Couple) -> &Bitset {
+        let (m, f) = couple;
+        &self.couples[m * self.columns + f]
+    }
+}
+
+impl Worldview {
+    /// Creates an empty store for worlds over `stride` contestants.
+    pub fn new(stride: usize) -> Worldview {
+        Worldview::with_capacity(stride, 0)
+    }
+
+    /// Creates an empty store for worlds over `stride` contestants, with room for `worlds` worlds.
+    pub fn with_capacity(stride: usize, worlds: usize) -> Worldview {
+        Worldview {
+            stride,
+            data: Storage::Owned(Vec::with_capacity(stride * worlds)),
+            index: OnceLock::new(),
+        }
+    }
+
+    /// Wraps a buffer of packed worlds over `stride` contestants into a store.
+    fn from_data(stride: usize, data: Vec<u8>) -> Worldview {
+        Worldview {
+            stride,
+            data: Storage::Owned(data),
+            index: OnceLock::new(),
+        }
+    }
+
+    /// Returns the kth world in the store.
+    pub fn get(&self, k: usize) -> &[u8] {
+        &self.data[k * self.stride..(k + 1) * self.stride]
+    }
+
+    /// Returns the kth world in the store, without checking that it exists.
+    ///
+    /// # Safety
+    ///
+    /// `k` must be less than `self.len()`.
+    pub unsafe fn get_unchecked(&self, k: usize) -> &[u8] {
+        self.data.get_unchecked(k * self.stride..(k + 1) * self.stride)
+    }
+
+    /// Returns the inverted couple index for this store, building it on first use.
+    ///
+    /// The worlds cannot tell how many columns there are when some column never appears (or when there are no worlds at
+    /// all), so the caller passes it in.
+    pub fn couple_index(&self, columns: usize) -> &Index {
+        self.index.get_or_init(|| Index::build(self, columns))
+    }
+
+    /// Returns whether the store is mapped from a cache on disk.
+    ///
+    /// Mapped stores are meant to be too large for memory, so they should be scanned rather than indexed.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.data, Storage::Mapped(_))
+    }
+
+    /// Returns whether the store holds any worlds.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns an iterator over every world in the store.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.stride.max(1))
+    }
+
+    /// Returns the number of worlds in the store.
+    pub fn len(&self) -> usize {
+        self.data.len() / self.stride.max(1)
+    }
+
+    /// Moves every world from another store onto the end of this one.
+    pub fn append(&mut self, other: Worldview) {
+        debug_assert_eq!(other.stride, self.stride);
+        self.data.owned().extend_from_slice(&other.data);
+        self.index = OnceLock::new();
+    }
+
+    /// Packs a world and appends it to the store.
+    pub fn push(&mut self, world: &[usize]) {
+        debug_assert_eq!(world.len(), self.stride);
+        self.data.owned().extend(world.iter().map(|&f| f as u8));
+        self.index = OnceLock::new();
+    }
+
+    /// Counts the worlds in a set that contain each couple, by scanning every one of them instead of using the index.
+    pub fn tally(&self, worlds: &Bitset, shape: (usize, usize)) -> Array2<u64> {
+        worlds
+            .par_iter()
+            .fold(
+                || Array2::zeros(shape),
+                |mut counts, k| {
+                    let world = unsafe { self.get_unchecked(k) };
+                    world.iter().enumerate().for_each(|(m, &f)| counts[(m, f as usize)] += 1);
+                    counts
+                },
+            )
+            .reduce(|| Array2::zeros(shape), |lhs, rhs| lhs + rhs)
+    }
+
+    /// Returns the number of contestants in every world in the store.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+/// Counts the places in which a world agrees with a packed ceremony.
+///
+/// This is written as a branchless sum over bytes so that it vectorises.
+#[inline]
+pub fn beams(world: &[u8], ceremony: &[u8]) -> usize {
+    world
+        .iter()
+        .zip(ceremony)
+        .map(|(lhs, rhs)| (lhs == rhs) as usize)
+        .sum()
+}
+
+/// Packs a match strip into bytes, so that it can be compared against the worlds in a store.
+pub fn pack(strip: &[usize]) -> Vec<u8> {
+    strip
+        .iter()
+        .map(|&f| match f {
+            NO_MATCH => PACKED_NO_MATCH,
+            _ => f as u8,
+        })
+        .collect()
+}
+
+/// Unpacks a match strip from bytes, undoing `pack`.
+pub fn unpack(packed: &[u8]) -> Vec<usize> {
+    packed
+        .iter()
+        .map(|&f| match f {
+            PACKED_NO_MATCH => NO_MATCH,
+            _ => f as usize,
+        })
+        .collect()
+}
+
+/// The number of worlds that each task generates when generating worlds in parallel.
+const CHUNK: usize = 1 << 12;
+
+/// Generates the kth permutation of the numbers 0..n, in lexicographic order.
 pub fn generate(n: usize, k: usize) -> Vec<usize> {
-    let mut atoms: Vec<usize> = (0..n).collect();
-    let mut v = vec![0; n];
-    (0..n).fold((&mut v, k), |(res, k), i| {
-        let item = k % (n - i);
-        let k = k / (n - i);
-        res[i] = atoms[item];
-        atoms.remove(item);
-        (res, k)
-    });
-    v
+    let mut world = vec![0; n];
+    unrank(k, &mut world);
+    world.into_iter().map(|f| f as usize).collect()
+}
+
+/// Writes the kth permutation of the numbers 0..n, in lexicographic order, into a packed world of length n.
+///
+/// This tracks the numbers that are still unused in a bitmask, so it never allocates; since n! must fit in a `usize`,
+/// n is at most 20.
+pub fn unrank(k: usize, world: &mut [u8]) {
+    let n = world.len();
+    let (mut unused, mut k) = ((1u32 << n) - 1, k);
+    for (i, slot) in world.iter_mut().enumerate() {
+        let place = (n - i - 1).factorial();
+        let mut digit = k / place;
+        k %= place;
+
+        // Take the digit-th lowest number that is still unused.
+        let mut candidates = unused;
+        while digit > 0 {
+            candidates &= candidates - 1;
+            digit -= 1;
+        }
+        let f = candidates.trailing_zeros();
+        unused &= !(1 << f);
+        *slot = f as u8;
+    }
+}
+
+/// Returns the position of a packed permutation of the numbers 0..n in lexicographic order, so that `unrank` undoes it.
+pub fn rank(world: &[u8]) -> usize {
+    let n = world.len();
+    let mut unused = (1u32 << n) - 1;
+    world.iter().enumerate().fold(0, |k, (i, &f)| {
+        // The digit is the number of smaller numbers that are still unused.
+        let digit = (unused & ((1 << f) - 1)).count_ones() as usize;
+        unused &= !(1 << f);
+        k + digit * (n - i - 1).factorial()
+    })
+}
+
+/// Steps a packed permutation forward to the next one in lexicographic order, returning `false` if it was the last.
+fn advance(world: &mut [u8]) -> bool {
+    let Some(i) = (1..world.len()).rev().find(|&i| world[i - 1] < world[i]) else {
+        return false;
+    };
+
+    let j = (i..world.len()).rev().find(|&j| world[j] > world[i - 1]).unwrap();
+    world.swap(i - 1, j);
+    world[i..].reverse();
+    true
+}
+
+/// Returns the number of worlds that `generate_all` generates.
+pub fn count_all(rows: usize, cols: usize) -> usize {
+    match rows {
+        0 => 0,
+        _ if rows == cols => rows.factorial(),
+        // Every choice of doubled contestants gives every permutation of rows onto slots, up to swapping doubled slots.
+        _ => (0..cols).combinations(rows - cols).count() * (rows.factorial() >> (rows - cols)),
+    }
+}
+
+/// Returns the number of worlds that `generate_matchings` generates.
+pub fn count_matchings(n: usize) -> usize {
+    match (n, n % 2) {
+        (0, _) | (_, 1) => 0,
+        _ => (1..n).step_by(2).product(),
+    }
+}
+
+/// Generates every permutation of the numbers 0..n in lexicographic order, in parallel.
+pub fn permutations(n: usize) -> Worldview {
+    generate_all(n, n)
+}
+
+/// Generates every world that matches each of `rows` contestants to one of `cols` contestants, such that every one of
+/// the `cols` contestants has either one or two matches.
+///
+/// When `rows == cols`, this is just every permutation of 0..n in the same order as `generate`.
+pub fn generate_all(rows: usize, cols: usize) -> Worldview {
+    let mut data = vec![0; count_all(rows, cols) * rows];
+    fill_all(rows, cols, &mut data);
+    Worldview::from_data(rows, data)
+}
+
+/// Writes every world that `generate_all` generates into a buffer of exactly the right size, in parallel.
+///
+/// Each choice of doubled contestants fills its own block with every arrangement of a multiset of columns, in which the
+/// doubled contestants appear twice and everybody else once; with equal groups, there is a single block of plain
+/// permutations. Each block is split into chunks; each chunk unranks its first world, and steps through the rest in
+/// place, so no world is ever allocated on its own.
+fn fill_all(rows: usize, cols: usize, data: &mut [u8]) {
+    if data.is_empty() {
+        return;
+    }
+
+    let block = rows.factorial() >> (rows - cols);
+    data.par_chunks_mut(rows * block)
+        .zip((0..cols).combinations(rows - cols).collect_vec())
+        .for_each(|(block, doubled)| {
+            let mut counts = vec![1u8; cols];
+            doubled.into_iter().for_each(|f| counts[f] = 2);
+
+            block.par_chunks_mut(rows * CHUNK).enumerate().for_each(|(c, chunk)| {
+                let mut worlds = chunk.chunks_exact_mut(rows);
+                let Some(first) = worlds.next() else {
+                    return;
+                };
+
+                unrank_multiset(c * CHUNK, &mut counts.clone(), first);
+                let mut previous: &[u8] = first;
+                for world in worlds {
+                    world.copy_from_slice(previous);
+                    // Stepping forward in lexicographic order skips over arrangements that only swap equal columns.
+                    advance(world);
+                    previous = world;
+                }
+            });
+        });
+}
+
+/// Writes the kth arrangement of a multiset of columns, in lexicographic order, into a packed world, where `counts[f]`
+/// is the number of times that column f appears (either one or two), and the counts add up to the length of the world.
+fn unrank_multiset(k: usize, counts: &mut [u8], world: &mut [u8]) {
+    let n = world.len();
+    let (mut k, mut doubled) = (k, counts.iter().filter(|&&count| count == 2).count());
+    for (i, slot) in world.iter_mut().enumerate() {
+        for (f, count) in counts.iter_mut().enumerate().filter(|(_, count)| **count > 0) {
+            // The rest of the world can be arranged in (n - i - 1)! ways, up to swapping the columns that still appear
+            // twice once f is taken.
+            let twice = doubled - (*count == 2) as usize;
+            let place = (n - i - 1).factorial() >> twice;
+            if k >= place {
+                k -= place;
+                continue;
+            }
+
+            *slot = f as u8;
+            *count -= 1;
+            doubled = twice;
+            break;
+        }
+    }
+}
+
+/// Generates every perfect matching of the numbers 0..n, where `world[i]` is the match of `i` (and so
+/// `world[world[i]] == i`).
+pub fn generate_matchings(n: usize) -> Worldview {
+    let mut data = vec![0; count_matchings(n) * n];
+    fill_matchings(n, &mut data);
+    Worldview::from_data(n, data)
+}
+
+/// Writes every world that `generate_matchings` generates into a buffer of exactly the right size.
+fn fill_matchings(n: usize, data: &mut [u8]) {
+    if !data.is_empty() {
+        extend_matchings(&mut vec![PACKED_NO_MATCH; n], &mut data.chunks_exact_mut(n));
+    }
+}
+
+/// Extends a partial matching in every possible way by matching its lowest unmatched element, writing each one out.
+fn extend_matchings<'a>(world: &mut [u8], out: &mut impl Iterator<Item = &'a mut [u8]>) {
+    let Some(i) = world.iter().position(|&j| j == PACKED_NO_MATCH) else {
+        out.next().expect("buffer is large enough").copy_from_slice(world);
+        return;
+    };
+
+    for j in (i + 1)..world.len() {
+        if world[j] == PACKED_NO_MATCH {
+            (world[i], world[j]) = (j as u8, i as u8);
+            extend_matchings(world, out);
+            (world[i], world[j]) = (PACKED_NO_MATCH, PACKED_NO_MATCH);
+        }
+    }
+}
+
+/// Maps every world of a season from a cache on disk, generating the cache first if it does not exist yet.
+///
+/// Each cache file holds every world of one shape of season with no evidence, so it can be shared by every season of
+/// that shape, and by every session. Caches are generated straight into a mapped file, so they never need to fit in
+/// memory.
+pub fn cached(kind: SeasonKind, rows: usize, cols: usize, dir: &Path) -> Result<Worldview> {
+    let (name, worlds) = match kind {
+        SeasonKind::Bipartite => (format!("bipartite-{rows}x{cols}.worlds"), count_all(rows, cols)),
+        SeasonKind::NonBipartite => (format!("pool-{rows}.worlds"), count_matchings(rows)),
+    };
+    let (path, len) = (dir.join(name), (worlds * rows) as u64);
+
+    let fresh = fs::metadata(&path).map(|meta| meta.len() == len).unwrap_or(false);
+    if !fresh && len > 0 {
+        info!("generating world cache at {}", path.display());
+        fs::create_dir_all(dir).context("cannot create cache directory")?;
+
+        // Generate into a temporary file, so that an interrupted run never leaves a partial cache behind.
+        let partial = path.with_extension("partial");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&partial)
+            .context("cannot create cache file")?;
+        file.set_len(len)?;
+
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        match kind {
+            SeasonKind::Bipartite => fill_all(rows, cols, &mut map),
+            SeasonKind::NonBipartite => fill_matchings(rows, &mut map),
+        }
+        map.flush()?;
+        fs::rename(&partial, &path).context("cannot move cache file into place")?;
+    }
+
+    if len == 0 {
+        return Ok(Worldview::new(rows));
+    }
+
+    let file = File::open(&path).context("cannot open cache file")?;
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(Worldview {
+        stride: rows,
+        data: Storage::Mapped(Arc::new(map)),
+        index: OnceLock::new(),
+    })
 }
@@ -5,8 +5,14 @@ pub mod prelude {
 
     pub type Result<T> = AnyhowResult<T, Error>;
 
+    pub use super::ayto::bitset::Bitset;
+    pub use super::ayto::components;
+    pub use super::ayto::enumerator::Enumerator;
+    pub use super::ayto::halves;
+    pub use super::ayto::permanent;
+    pub use super::ayto::sampler::Sampler;
     pub use super::ayto::types::*;
-    pub use super::ayto::worldview;
+    pub use super::ayto::worldview::{self, Worldview};
     pub use super::ayto::Season;
 
     pub use anyhow::{anyhow, Context, Error};
@@ -1,6 +1,23 @@
 use ayto_rs::prelude::*;
 use clap::*;
 use std::io::{stdin, stdout, Write};
+use std::path::PathBuf;
+
+/// The number of worlds that approximate mode samples by default.
+const DEFAULT_SAMPLES: usize = 10_000;
+
+/// The most memory that the worlds of a new season can take up once materialised, at one byte per row of each world,
+/// before it falls back to sampling them.
+const MAX_MATERIALISED_BYTES: usize = 2 << 30;
+
+/// The number of candidates that a local search for the best ceremony proposes by default.
+const DEFAULT_ITERATIONS: usize = 10_000;
+
+/// The number of first moves that a lookahead lists, besides the greedy one.
+const MAX_PLANS: usize = 5;
+
+/// The number of moves that a decision tree plans ahead by default.
+const DEFAULT_DEPTH: usize = 3;
 
 #[derive(Clone, Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -19,7 +36,13 @@ enum Commands {
     ApplyTruthBooth(ApplyTruthBoothArgs),
     BestCeremony(BestCeremonyArgs),
     BestTruthBooth(BestTruthBoothArgs),
+    DecisionTree(DecisionTreeArgs),
+    Lookahead(LookaheadArgs),
+    Materialise(MaterialiseArgs),
+    Sample(SampleArgs),
+    Solve,
     Spread(SpreadArgs),
+    TruthCover(TruthCoverArgs),
     Worlds,
 }
 
@@ -34,7 +57,13 @@ impl std::fmt::Display for Commands {
             Commands::ApplyTruthBooth(_) => "apply-truth-booth",
             Commands::BestCeremony(_) => "best-ceremony",
             Commands::BestTruthBooth(_) => "best-truth-booth",
+            Commands::DecisionTree(_) => "decision-tree",
+            Commands::Lookahead(_) => "lookahead",
+            Commands::Materialise(_) => "materialise",
+            Commands::Sample(_) => "sample",
+            Commands::Solve => "solve",
             Commands::Spread(_) => "spread",
+            Commands::TruthCover(_) => "truth-cover",
             Commands::Worlds => "worlds",
         };
         write!(f, "{repr}")
@@ -46,8 +75,9 @@ struct ApplyCeremonyArgs {
     beams: usize,
     names: Vec<String>,
 
-    #[arg(short, long)]
-    offline: bool,
+    // Tables are kept current after every event now, so this is only accepted so that older scripts still parse.
+    #[arg(short = 'o', long = "offline", hide = true)]
+    _offline: bool,
 }
 
 #[derive(Clone, Debug, Args)]
@@ -58,26 +88,99 @@ struct ApplyTruthBoothArgs {
     #[arg(short, long)]
     incorrect: bool,
 
-    #[arg(short, long)]
-    offline: bool,
+    // Tables are kept current after every event now, so this is only accepted so that older scripts still parse.
+    #[arg(short = 'o', long = "offline", hide = true)]
+    _offline: bool,
 }
 
 #[derive(Clone, Debug, Args)] 
 struct BestCeremonyArgs {
-    #[arg(short, long)]
+    #[arg(long, value_enum, default_value_t = Objective::Minimax)]
+    objective: Objective,
+
+    // This used to be the only other objective, so it is still accepted so that older scripts still parse.
+    #[arg(short, long, hide = true, conflicts_with = "objective")]
     naive: bool,
+
+    #[arg(short, long, conflicts_with = "search")]
+    all: bool,
+
+    #[arg(long, conflicts_with_all = ["all", "search"])]
+    swaps: Option<usize>,
+
+    #[arg(short, long)]
+    search: bool,
+
+    #[arg(short, long, default_value_t = DEFAULT_ITERATIONS)]
+    iterations: usize,
+
+    #[arg(short, long, value_parser = _parse_seconds)]
+    time: Option<Duration>,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
 }
 
 #[derive(Clone, Debug, Args)]
 struct BestTruthBoothArgs {
     #[arg(short, long)]
     constrain: bool,
+
+    /// Only minimax and information apply to a truth booth, and they are equivalent: with just two outcomes, the couple
+    /// that leaves the fewest worlds in the worst case also reveals the most bits, so both pick the same couples.
+    #[arg(long, value_enum, default_value_t = Objective::Minimax)]
+    objective: Objective,
+}
+
+#[derive(Clone, Debug, Args)]
+struct DecisionTreeArgs {
+    #[arg(value_enum, default_values_t = [Move::Truth, Move::Ceremony])]
+    moves: Vec<Move>,
+
+    #[arg(short, long, default_value_t = DEFAULT_DEPTH)]
+    depth: usize,
+
+    #[arg(long, value_enum, default_value_t = Objective::Minimax)]
+    objective: Objective,
+
+    #[arg(short, long)]
+    exact: bool,
+
+    #[arg(short, long)]
+    json: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Args)]
+struct LookaheadArgs {
+    #[arg(value_enum, default_values_t = [Move::Truth, Move::Ceremony])]
+    moves: Vec<Move>,
+
+    #[arg(long, value_enum, default_value_t = Objective::Minimax)]
+    objective: Objective,
 }
 
 #[derive(Clone, Debug, Args)]
 struct NewGameArgs {
     n: usize,
     names: Vec<String>,
+
+    #[arg(short, long, default_value_t = 0)]
+    extra: usize,
+
+    #[arg(short, long, conflicts_with = "extra")]
+    pool: bool,
+
+    #[arg(short, long)]
+    lazy: bool,
+
+    #[arg(short, long)]
+    cache: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Args)]
+struct MaterialiseArgs {
+    #[arg(short, long)]
+    cache: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Args)]
@@ -86,12 +189,27 @@ struct PrintArgs {
     probabilities: bool,
 }
 
+#[derive(Clone, Debug, Args)]
+struct SampleArgs {
+    #[arg(short, long, default_value_t = DEFAULT_SAMPLES)]
+    samples: usize,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
 #[derive(Clone, Debug, Args)]
 struct SpreadArgs {
     names: Vec<String>,
 }
 
-fn main() -> () {
+#[derive(Clone, Debug, Args)]
+struct TruthCoverArgs {
+    #[arg(short, long)]
+    tree: bool,
+}
+
+fn main() {
     pretty_env_logger::init();
     println!();
 
@@ -103,12 +221,11 @@ fn main() -> () {
 fn _main() -> Result<()> {
     let mut season: Option<Season> = None;
     let mut constraint: Option<Vec<CoupleOutput>> = None;
-    let mut view: Vec<Vec<usize>>;
 
     loop {
         let input = _read()?;
 
-        if input.trim().len() == 0 || input.trim().starts_with("#") {
+        if input.trim().is_empty() || input.trim().starts_with("#") {
             continue;
         }
 
@@ -116,7 +233,7 @@ fn _main() -> Result<()> {
         cmdline.extend(input.split_ascii_whitespace());
         let try_parse = Root::try_parse_from(cmdline);
         let Ok(cmd) = try_parse else {
-            println!("\n{}", try_parse.err().unwrap().to_string());
+            println!("\n{}", try_parse.err().unwrap());
             continue;
         };
 
@@ -124,28 +241,58 @@ fn _main() -> Result<()> {
             let start = Instant::now();
 
             match &cmd.cmd {
-                Commands::NewGame(NewGameArgs { n, names }) => {
-                    if names.len() != 2 * n {
+                Commands::NewGame(NewGameArgs {
+                    n,
+                    names,
+                    extra,
+                    pool,
+                    lazy,
+                    cache,
+                }) => {
+                    // The first group may be larger, with its extra contestants sharing matches in the second.
+                    if names.len() != 2 * n + extra {
                         println!(
                             "err: expected {} names; received {} instead.",
-                            2 * n,
+                            2 * n + extra,
                             names.len()
                         );
                         break 'outer;
                     }
 
-                    let chunks = names.chunks(*n).collect::<Vec<_>>();
-                    let (m, f) = (chunks[0], chunks[1]);
-                    let (m, f) = (
-                        m.iter().map(|n| n.as_str()).sorted().collect(),
-                        f.iter().map(|n| n.as_str()).sorted().collect(),
-                    );
-
                     season = None;
-                    view = (0..n.factorial())
-                        .map(|k| worldview::generate(*n, k))
-                        .collect();
-                    let r = Season::new(&m, &f, &view);
+                    let r = if *pool {
+                        // Everyone is in one group, and anyone could be matched with anyone else.
+                        let pool: Vec<&str> = names.iter().map(|n| n.as_str()).sorted().collect();
+                        Season::new_nonbipartite(&pool)
+                    } else {
+                        let (m, f) = names.split_at(n + extra);
+                        let (m, f): (Vec<&str>, Vec<&str>) = (
+                            m.iter().map(|n| n.as_str()).sorted().collect(),
+                            f.iter().map(|n| n.as_str()).sorted().collect(),
+                        );
+                        Season::new(&m, &f)
+                    };
+
+                    // Lazy seasons only record evidence until they are materialised, which lets large seasons skip
+                    // every world that the evidence already rules out. Seasons with too many worlds to materialise
+                    // are sampled instead, unless they can be mapped from a cache on disk.
+                    let r = r.and_then(|mut s| {
+                        let bytes = s.num_worlds().saturating_mul(s.rows().len());
+                        match (*lazy, cache, s.engine().is_none() || bytes > MAX_MATERIALISED_BYTES) {
+                            (true, _, _) => (),
+                            (_, Some(dir), _) => {
+                                s.materialise_cached(dir)?;
+                            }
+                            (_, _, true) => {
+                                println!("note: too many worlds to fit in memory; sampling {DEFAULT_SAMPLES} of them instead.");
+                                s.sample(DEFAULT_SAMPLES, 0)?;
+                            }
+                            _ => {
+                                s.materialise()?;
+                            }
+                        }
+                        Ok(s)
+                    });
 
                     match r {
                         Ok(s) => {
@@ -190,7 +337,7 @@ fn _main() -> Result<()> {
                 Commands::ApplyCeremony(ApplyCeremonyArgs {
                     beams,
                     names,
-                    offline,
+                    ..
                 }) => {
                     let Some(season) = season.as_mut() else {
                         _err_no_season();
@@ -218,7 +365,7 @@ fn _main() -> Result<()> {
                         .map(|(m, f)| (m.as_str(), f.as_str()))
                         .collect();
 
-                    if let Err(e) = season.apply_ceremony(couples, *beams, !*offline) {
+                    if let Err(e) = season.apply_ceremony(couples, *beams) {
                         println!("err: {e}");
                     }
 
@@ -228,7 +375,7 @@ fn _main() -> Result<()> {
                     m,
                     f,
                     incorrect,
-                    offline,
+                    ..
                 }) => {
                     let Some(season) = season.as_mut() else {
                         _err_no_season();
@@ -237,28 +384,63 @@ fn _main() -> Result<()> {
 
                     let couple = (m.as_str(), f.as_str());
 
-                    if let Err(e) = season.apply_truth(couple, !*incorrect, !*offline) {
+                    if let Err(e) = season.apply_truth(couple, !*incorrect) {
                         println!("err: {e}");
                     }
                 }
-                Commands::BestCeremony(BestCeremonyArgs { naive }) => {
+                Commands::BestCeremony(BestCeremonyArgs {
+                    objective,
+                    naive,
+                    all,
+                    swaps,
+                    search,
+                    iterations,
+                    time,
+                    seed,
+                }) => {
                     let Some(season) = season.as_mut() else {
                         _err_no_season();
                         break 'outer;
                     };
 
-                    let r = season.find_best_ceremony(*naive);
+                    let objective = match naive {
+                        true => Objective::Beams,
+                        _ => *objective,
+                    };
+
+                    let r = match search {
+                        true => season.search_best_ceremony(
+                            objective,
+                            Annealing {
+                                iterations: *iterations,
+                                time: *time,
+                                seed: *seed,
+                            },
+                        ),
+                        _ => season.find_best_ceremony(
+                            objective,
+                            match (all, swaps) {
+                                (true, _) => Candidates::All,
+                                (_, Some(k)) => Candidates::Swaps(*k),
+                                _ => Candidates::Worlds,
+                            },
+                        ),
+                    };
 
                     match r {
                         Ok(candidates) => {
-                            if let Some(best_ceremony) = candidates.get(0) {
-                                let ceremony_input: Vec<CoupleInput> = best_ceremony.iter().map(|(m, f)| {
+                            if let Some(CeremonyScore { couples, score }) = candidates.first() {
+                                let ceremony_input: Vec<CoupleInput> = couples.iter().map(|(m, f)| {
                                     (m.as_str(), f.as_str())
                                 }).collect();
                                 if let Err(e) = season.speculate(ceremony_input.clone(), "best possible ceremony") {
                                     println!("err: {e}");
                                 }
-                                println!("");
+                                println!("{}", _describe(objective, *score));
+                                if let Ok(false) = season.is_possible(&ceremony_input) {
+                                    println!("note: this line-up has already been ruled out, but it splits the remaining worlds best.");
+                                }
+                                println!();
                                 if let Err(e) = season.spread(ceremony_input) {
                                     println!("err: {e}");
                                 }
@@ -271,7 +453,7 @@ fn _main() -> Result<()> {
                         }
                     }
                 }
-                Commands::BestTruthBooth(BestTruthBoothArgs { constrain }) => {
+                Commands::BestTruthBooth(BestTruthBoothArgs { constrain, objective }) => {
                     let Some(season) = season.as_mut() else {
                         _err_no_season();
                         break 'outer;
@@ -283,15 +465,24 @@ fn _main() -> Result<()> {
                         .map(|v| v.iter().map(|(m, f)| (m.as_str(), f.as_str())).collect());
                     let this_constraint = this_constraint.as_ref();
 
-                    let r = season.find_best_truth(if *constrain { this_constraint } else { None });
+                    let r = season.find_best_truth(if *constrain { this_constraint } else { None }, *objective);
 
                     match r {
                         Ok(candidates) => {
-                            if let Some((m, f)) = candidates.get(0) {
+                            if let Some(TruthScore { couple: (m, f), .. }) = candidates.first() {
                                 let couple_input = (m.as_str(), f.as_str()); 
                                 if let Err(e) = season.speculate(vec![couple_input], "best possible truth booth") {
                                     println!("err: {e}");
                                 }
+                                // Every couple that ties for the best is listed, starting with the one shown above.
+                                for TruthScore { couple: (m, f), worst, bits } in &candidates {
+                                    println!("{m} & {f}: {bits:.3} bits expected, leaves at most {worst} of the remaining worlds");
+                                }
+                                if let (Some(_), Ok(count)) = (season.sampling(), season.count(couple_input)) {
+                                    let (p, margin) = season.estimate(count);
+                                    let samples = season.num_worlds();
+                                    println!("{m} & {f} match in {:.1}±{:.1}% of {samples} samples", p * 100.0, margin * 100.0);
+                                }
                             } else {
                                 println!("There are no couples; did you enter a contradiction?");
                             }
@@ -301,6 +492,114 @@ fn _main() -> Result<()> {
                         }
                     }
                 },
+                Commands::DecisionTree(DecisionTreeArgs { moves, depth, objective, exact, json }) => {
+                    let Some(season) = season.as_mut() else {
+                        _err_no_season();
+                        break 'outer;
+                    };
+
+                    let tree = match season.decision_tree(moves, *depth, *objective, *exact) {
+                        Ok(tree) => tree,
+                        Err(e) => {
+                            println!("err: {e}");
+                            break 'outer;
+                        }
+                    };
+
+                    if tree.worlds == 0 {
+                        println!("There are no worlds; did you enter a contradiction?");
+                        break 'outer;
+                    }
+
+                    _print_decision(&tree, "", 0);
+                    if let Some(path) = json {
+                        let r = serde_json::to_string_pretty(&tree)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|json| Ok(std::fs::write(path, json)?));
+                        if let Err(e) = r {
+                            println!("err: {e}");
+                        }
+                    }
+                }
+                Commands::Lookahead(LookaheadArgs { moves, objective }) => {
+                    let Some(season) = season.as_mut() else {
+                        _err_no_season();
+                        break 'outer;
+                    };
+
+                    let plans = match season.lookahead(moves, *objective) {
+                        Ok(plans) => plans,
+                        Err(e) => {
+                            println!("err: {e}");
+                            break 'outer;
+                        }
+                    };
+
+                    if plans.is_empty() {
+                        println!("There are no worlds; did you enter a contradiction?");
+                        break 'outer;
+                    }
+
+                    println!("first moves, looking ahead over {}:", moves.iter().map(|m| format!("{m:?}").to_lowercase()).join(", "));
+                    if moves.first() == Some(&Move::Ceremony) {
+                        println!("note: only the best ceremony under each objective is tried as the first move.");
+                    }
+                    for (i, plan) in plans.iter().enumerate() {
+                        if i >= MAX_PLANS && !plan.greedy {
+                            continue;
+                        }
+
+                        let greedy = if plan.greedy { " (greedy)" } else { "" };
+                        println!(
+                            "{:>3}. {}: {:.2} worlds expected, at most {}{greedy}",
+                            i + 1,
+                            plan.couples.iter().map(|(m, f)| format!("{m} & {f}")).join(", "),
+                            plan.expected,
+                            plan.worst
+                        );
+                    }
+                }
+                Commands::Materialise(MaterialiseArgs { cache }) => {
+                    let Some(season) = season.as_mut() else {
+                        _err_no_season();
+                        break 'outer;
+                    };
+
+                    let r = match cache {
+                        Some(dir) => season.materialise_cached(dir),
+                        None => season.materialise(),
+                    };
+
+                    if let Err(e) = r {
+                        println!("err: {e}");
+                    }
+                }
+                Commands::Sample(SampleArgs { samples, seed }) => {
+                    let Some(season) = season.as_mut() else {
+                        _err_no_season();
+                        break 'outer;
+                    };
+
+                    if let Err(e) = season.sample(*samples, *seed) {
+                        println!("err: {e}");
+                    }
+                }
+                Commands::Solve => {
+                    let Some(season) = season.as_mut() else {
+                        _err_no_season();
+                        break 'outer;
+                    };
+
+                    match season.solve() {
+                        Ok(Solution { worst, worst_step, expected, expected_step }) => {
+                            println!("in the worst case, {worst} more moves identify the perfect line-up{}", _describe_step(worst_step.as_ref()));
+                            println!("on average, {expected:.3} more moves identify the perfect line-up{}", _describe_step(expected_step.as_ref()));
+                        }
+                        Err(e) => {
+                            println!("err: {e}");
+                        }
+                    }
+                }
                 Commands::Spread(SpreadArgs { names }) => {
                     let Some(season) = season.as_mut() else {
                         _err_no_season();
@@ -333,13 +632,60 @@ fn _main() -> Result<()> {
                         println!("err: {e}");
                     }
                 },
+                Commands::TruthCover(TruthCoverArgs { tree: print_tree }) => {
+                    let Some(season) = season.as_mut() else {
+                        _err_no_season();
+                        break 'outer;
+                    };
+
+                    let TruthCover { couples, minimal, adaptive, tree } = match season.truth_cover() {
+                        Ok(cover) => cover,
+                        Err(e) => {
+                            println!("err: {e}");
+                            break 'outer;
+                        }
+                    };
+
+                    match season.num_worlds() {
+                        0 => println!("There are no worlds; did you enter a contradiction?"),
+                        1 => println!("The perfect line-up is already identified."),
+                        _ => {
+                            println!(
+                                "{}: {}",
+                                _describe_truths(couples.len()),
+                                couples.iter().map(|(m, f)| format!("{m} & {f}")).join(", ")
+                            );
+                            match (minimal, adaptive) {
+                                (true, _) => {}
+                                (_, Some(adaptive)) => println!("note: the search gave up before it could rule out every smaller set; none smaller than {adaptive} can work."),
+                                _ => println!("note: the search gave up before it could rule out every smaller set."),
+                            }
+                            match (adaptive, tree) {
+                                (Some(adaptive), Some(tree)) => {
+                                    println!("adaptively, {}{}", _describe_truths(adaptive), _describe_step(tree.step.as_ref()));
+                                    if *print_tree {
+                                        _print_decision(&tree, "", 2);
+                                    }
+                                }
+                                _ => println!("adaptively: gave up; try again once fewer worlds remain."),
+                            }
+                        }
+                    }
+                }
                 Commands::Worlds => {
                     let Some(season) = season.as_mut() else {
                         _err_no_season();
                         break 'outer;
                     };
 
-                    let worlds = season.worlds();
+                    let worlds = match season.worlds() {
+                        Ok(worlds) => worlds,
+                        Err(e) => {
+                            println!("err: {e}");
+                            break 'outer;
+                        }
+                    };
+
                     println!("worlds:");
                     for (i, w) in worlds.iter().enumerate() {
                         println!("{:>3}. {}", i + 1, w.iter().map(|(m, f)| format!("{m} & {f}")).join(", "));
@@ -354,10 +700,72 @@ fn _main() -> Result<()> {
     }
 }
 
-fn _err_no_season() -> () {
+fn _err_no_season() {
     println!("err: no season; use `newgame` to create one!");
 }
 
+fn _describe(objective: Objective, score: f64) -> String {
+    match objective {
+        Objective::Minimax => format!("leaves at most {score} of the remaining worlds"),
+        Objective::FollowUp => format!("leaves at most {score} of the remaining worlds after the best follow-up truth booth"),
+        Objective::Information => format!("{score:.3} bits expected"),
+        Objective::Blackout => format!("lights up at least {score} of the beams"),
+        Objective::Beams => format!("{score:.2} beams expected"),
+    }
+}
+
+fn _describe_step(step: Option<&Step>) -> String {
+    let Some(Step { kind, couples }) = step else {
+        return String::new();
+    };
+
+    let couples = couples.iter().map(|(m, f)| format!("{m} & {f}")).join(", ");
+    match kind {
+        Move::Truth => format!(", starting with a truth booth on {couples}"),
+        Move::Ceremony => format!(", starting with a ceremony of {couples}"),
+    }
+}
+
+fn _describe_truths(count: usize) -> String {
+    match count {
+        1 => "1 truth booth always suffices".to_owned(),
+        count => format!("{count} truth booths always suffice"),
+    }
+}
+
+fn _print_decision(decision: &Decision, outcome: &str, indent: usize) {
+    let worlds = match decision.worlds {
+        1 => "1 world".to_owned(),
+        worlds => format!("{worlds} worlds"),
+    };
+    let next = match (&decision.solution, &decision.step) {
+        (Some(couples), _) => format!(": {}", couples.iter().map(|(m, f)| format!("{m} & {f}")).join(", ")),
+        (_, Some(Step { kind: Move::Truth, couples })) => {
+            format!(", truth booth on {}", couples.iter().map(|(m, f)| format!("{m} & {f}")).join(", "))
+        }
+        (_, Some(Step { kind: Move::Ceremony, couples })) => {
+            format!(", ceremony of {}", couples.iter().map(|(m, f)| format!("{m} & {f}")).join(", "))
+        }
+        _ => String::new(),
+    };
+    println!("{:indent$}{outcome}{worlds}{next}", "");
+
+    for Branch { outcome, decision } in &decision.branches {
+        let outcome = match outcome {
+            Outcome::Match(true) => "match: ".to_owned(),
+            Outcome::Match(false) => "no match: ".to_owned(),
+            Outcome::Beams(1) => "1 beam: ".to_owned(),
+            Outcome::Beams(beams) => format!("{beams} beams: "),
+        };
+        _print_decision(decision, &outcome, indent + 2);
+    }
+}
+
+fn _parse_seconds(s: &str) -> std::result::Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| "expected a non-negative number of seconds".into())
+}
+
 fn _print(prompt: &str) -> Result<()> {
     print!("{prompt}");
     stdout().flush()?;